@@ -1,5 +1,8 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
 use futures::{Stream, StreamExt};
 use reqwest::StatusCode;
 use reqwest_eventsource::{Event, EventSource, RequestBuilderExt};
@@ -11,10 +14,13 @@ use uuid::Uuid;
 use crate::error::Error;
 use crate::inference::providers::gcp_vertex_gemini::GCPCredentials;
 use crate::inference::providers::provider_trait::InferenceProvider;
-use crate::inference::types::{ContentBlock, ContentBlockChunk, Latency, Role, Text, TextChunk};
 use crate::inference::types::{
-    ModelInferenceRequest, ProviderInferenceResponse, ProviderInferenceResponseChunk,
-    ProviderInferenceResponseStream, RequestMessage, Usage,
+    ContentBlock, ContentBlockChunk, File, FileKind, Image, ImageKind, Latency, Role, Text,
+    TextChunk, Thought, ThoughtChunk,
+};
+use crate::inference::types::{
+    ModelInferenceRequest, ModelInferenceRequestJsonMode, ProviderInferenceResponse,
+    ProviderInferenceResponseChunk, ProviderInferenceResponseStream, RequestMessage, Usage,
 };
 use crate::tool::{ToolCall, ToolCallChunk, ToolChoice, ToolConfig};
 
@@ -28,10 +34,47 @@ pub struct GCPVertexAnthropicProvider {
     pub audience: String,
     pub credentials: Option<GCPCredentials>,
     pub model_id: String,
+    /// When set, tools are not sent through the native Anthropic `tools` API. Instead they are
+    /// described in the system prompt and the model is asked to emit calls as a small XML schema.
+    /// This lets deployments that lack the native tool-use beta still call functions.
+    pub use_prompted_tools: bool,
+    /// Arbitrary provider-specific fields deep-merged into the outgoing request body (user keys
+    /// win over the fields this crate generates, except the keys in [`RESERVED_EXTRA_BODY_KEYS`],
+    /// which are rejected with an error). Lets callers use newly-released Anthropic models or
+    /// beta parameters before this crate adds typed support for them.
+    pub extra_body: Option<serde_json::Map<String, Value>>,
+    /// Whether this model supports Anthropic's native tool-use API at all. When `false`, a
+    /// request that supplies tools is rejected up front instead of failing with a server error.
+    pub supports_function_calling: bool,
+    /// Whether this model supports calling more than one tool in a single turn. When `false`,
+    /// `disable_parallel_tool_use` is always forced on in the outgoing `tool_choice`, regardless
+    /// of what the request asks for.
+    pub supports_parallel_tool_use: bool,
 }
 
 const ANTHROPIC_API_VERSION: &str = "vertex-2023-10-16";
 
+/// The XML tag Anthropic models are instructed to close a prompted tool call with.
+/// Registered as a stop sequence so generation halts right after the block.
+const PROMPTED_TOOLS_STOP_SEQUENCE: &str = "</function_calls>";
+
+/// The XML tag Anthropic models are instructed to open a prompted tool call with.
+const PROMPTED_TOOLS_OPEN_TAG: &str = "<function_calls>";
+
+/// Anthropic has no native JSON mode. When `json_mode` is on and an `output_schema` is given, we
+/// synthesize a tool with this reserved name, force the model to call it via `tool_choice`, and
+/// surface its arguments back to the caller as a text block instead of a tool call.
+const IMPLICIT_TOOL_NAME: &str = "respond";
+
+/// Whether `GCPVertexAnthropicRequestBody::new` should force the model to "call" the
+/// [`IMPLICIT_TOOL_NAME`] tool to emit schema-conforming JSON instead of a native tool call.
+fn json_mode_forces_tool(request: &ModelInferenceRequest) -> bool {
+    matches!(
+        request.json_mode,
+        ModelInferenceRequestJsonMode::On | ModelInferenceRequestJsonMode::Strict
+    ) && request.output_schema.is_some()
+}
+
 impl InferenceProvider for GCPVertexAnthropicProvider {
     /// Anthropic non-streaming API request
     async fn infer<'a>(
@@ -42,7 +85,7 @@ impl InferenceProvider for GCPVertexAnthropicProvider {
         let credentials = self.credentials.as_ref().ok_or(Error::ApiKeyMissing {
             provider_name: "GCP Vertex Anthropic".to_string(),
         })?;
-        let request_body = GCPVertexAnthropicRequestBody::new(request)?;
+        let request_body = self.build_request_body(request)?;
         let token = credentials.get_jwt_token(&self.audience)?;
         let start_time = Instant::now();
         let res = http_client
@@ -66,7 +109,11 @@ impl InferenceProvider for GCPVertexAnthropicProvider {
                 message: format!("Error parsing JSON response: {e}: {response}"),
             })?;
 
-            let response_with_latency = GCPVertexAnthropicResponseWithLatency { response, latency };
+            let response_with_latency = GCPVertexAnthropicResponseWithLatency {
+                response,
+                latency,
+                use_prompted_tools: self.use_prompted_tools,
+            };
             Ok(response_with_latency.try_into()?)
         } else {
             let response_code = res.status();
@@ -94,7 +141,7 @@ impl InferenceProvider for GCPVertexAnthropicProvider {
         let credentials = self.credentials.as_ref().ok_or(Error::ApiKeyMissing {
             provider_name: "GCP Vertex Anthropic".to_string(),
         })?;
-        let request_body = GCPVertexAnthropicRequestBody::new(request)?;
+        let request_body = self.build_request_body(request)?;
         let token = credentials.get_jwt_token(&self.audience)?;
         let start_time = Instant::now();
         let event_source = http_client
@@ -106,7 +153,11 @@ impl InferenceProvider for GCPVertexAnthropicProvider {
             .map_err(|e| Error::InferenceClient {
                 message: format!("Error sending request to Anthropic: {e}"),
             })?;
-        let mut stream = Box::pin(stream_anthropic(event_source, start_time));
+        let mut stream = Box::pin(stream_anthropic(
+            event_source,
+            start_time,
+            self.use_prompted_tools,
+        ));
         let chunk = match stream.next().await {
             Some(Ok(chunk)) => chunk,
             Some(Err(e)) => return Err(e),
@@ -124,6 +175,70 @@ impl InferenceProvider for GCPVertexAnthropicProvider {
     }
 }
 
+impl GCPVertexAnthropicProvider {
+    /// Builds the JSON request body, splicing in `extra_body` (if set) on top of the typed fields
+    /// this crate generates.
+    fn build_request_body(&self, request: &ModelInferenceRequest) -> Result<Value, Error> {
+        let request_body = GCPVertexAnthropicRequestBody::new(
+            request,
+            self.use_prompted_tools,
+            self.supports_function_calling,
+            self.supports_parallel_tool_use,
+        )?;
+        let mut request_body =
+            serde_json::to_value(&request_body).map_err(|e| Error::InferenceClient {
+                message: format!("Error serializing request body: {e}"),
+            })?;
+        if let Some(extra_body) = &self.extra_body {
+            merge_extra_body(&mut request_body, extra_body)?;
+        }
+        Ok(request_body)
+    }
+}
+
+/// Top-level keys `extra_body` may not set, because this crate must fully control them to keep
+/// the request it builds internally consistent (e.g. `messages` carries the `tool_use`/
+/// `tool_result` `id` pairings the provider generates and relies on matching up).
+const RESERVED_EXTRA_BODY_KEYS: &[&str] = &["anthropic_version", "messages"];
+
+/// Deep-merges `overlay` into `base`, preferring `overlay`'s values on conflicts. Nested objects
+/// are merged key-by-key rather than replaced wholesale; any other value (including arrays) is
+/// simply overwritten. Used to splice a caller's `extra_body` into the request we generate.
+///
+/// Rejects `overlay` outright if it sets any of [`RESERVED_EXTRA_BODY_KEYS`] at the top level,
+/// rather than silently letting it clobber a field this crate must control.
+fn merge_extra_body(
+    base: &mut Value,
+    overlay: &serde_json::Map<String, Value>,
+) -> Result<(), Error> {
+    if let Some(key) = overlay
+        .keys()
+        .find(|key| RESERVED_EXTRA_BODY_KEYS.contains(&key.as_str()))
+    {
+        return Err(Error::InvalidRequest {
+            message: format!("`extra_body` may not set reserved field `{key}`"),
+        });
+    }
+    merge_extra_body_unchecked(base, overlay);
+    Ok(())
+}
+
+fn merge_extra_body_unchecked(base: &mut Value, overlay: &serde_json::Map<String, Value>) {
+    let Value::Object(base_map) = base else {
+        return;
+    };
+    for (key, value) in overlay {
+        match (base_map.get_mut(key), value) {
+            (Some(existing @ Value::Object(_)), Value::Object(overlay_map)) => {
+                merge_extra_body_unchecked(existing, overlay_map);
+            }
+            _ => {
+                base_map.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
 /// Maps events from Anthropic into the TensorZero format
 /// Modified from the example [here](https://github.com/64bit/async-openai/blob/5c9c817b095e3bacb2b6c9804864cdf8b15c795e/async-openai/src/client.rs#L433)
 /// At a high level, this function is handling low-level EventSource details and mapping the objects returned by Anthropic into our `InferenceResultChunk` type
@@ -131,11 +246,18 @@ impl InferenceProvider for GCPVertexAnthropicProvider {
 fn stream_anthropic(
     mut event_source: EventSource,
     start_time: Instant,
+    use_prompted_tools: bool,
 ) -> impl Stream<Item = Result<ProviderInferenceResponseChunk, Error>> {
     async_stream::stream! {
         let inference_id = Uuid::now_v7();
-        let mut current_tool_id : Option<String> = None;
-        let mut current_tool_name: Option<String> = None;
+        // Keyed by content block index so that parallel tool calls (more than one `tool_use`
+        // block open at once) don't get their `InputJsonDelta`s attributed to the wrong tool.
+        // The third tuple element accumulates the raw `InputJsonDelta` fragments seen so far so
+        // that, if the stream ends mid-argument, `ContentBlockStop` can repair the truncated JSON.
+        let mut open_tool_calls: HashMap<u32, (String, String, String)> = HashMap::new();
+        // Holds text deltas while a `<function_calls>` block may be in flight, since partial XML
+        // can't be parsed into a `ToolCallChunk` until the closing tag arrives.
+        let mut prompted_tool_buffer: Option<String> = use_prompted_tools.then(String::new);
         while let Some(ev) = event_source.next().await {
             match ev {
                 Err(e) => {
@@ -155,6 +277,24 @@ fn stream_anthropic(
                             });
                         // Anthropic streaming API docs specify that this is the last message
                         if let Ok(GCPVertexAnthropicStreamMessage::MessageStop) = data {
+                            // If the stream ended without ever seeing the stop sequence (the
+                            // common case when the model didn't call a tool this turn), whatever
+                            // text is still sitting in the buffer was never flushed. Emit it now
+                            // rather than silently dropping it.
+                            if let Some(buffer) = prompted_tool_buffer.take() {
+                                if !buffer.is_empty() {
+                                    yield Ok(ProviderInferenceResponseChunk::new(
+                                        inference_id,
+                                        vec![ContentBlockChunk::Text(TextChunk {
+                                            text: buffer,
+                                            id: "0".to_string(),
+                                        })],
+                                        None,
+                                        message.data,
+                                        start_time.elapsed(),
+                                    ));
+                                }
+                            }
                             break;
                         }
 
@@ -163,14 +303,22 @@ fn stream_anthropic(
                                 data,
                                 inference_id,
                                 start_time.elapsed(),
-                                &mut current_tool_id,
-                                &mut current_tool_name,
+                                &mut open_tool_calls,
                             )
                         });
 
                         match response {
                             Ok(None) => {},
-                            Ok(Some(stream_message)) => yield Ok(stream_message),
+                            Ok(Some(stream_message)) => {
+                                match prompted_tool_buffer.as_mut() {
+                                    Some(buffer) => match buffer_prompted_tool_chunk(buffer, stream_message) {
+                                        Ok(Some(msg)) => yield Ok(msg),
+                                        Ok(None) => {}
+                                        Err(e) => yield Err(e),
+                                    },
+                                    None => yield Ok(stream_message),
+                                }
+                            }
                             Err(e) => yield Err(e),
                         }
                     }
@@ -206,9 +354,25 @@ impl From<Role> for GCPVertexAnthropicRole {
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 enum GCPVertexAnthropicToolChoice<'a> {
-    Auto,
-    Any,
-    Tool { name: &'a str },
+    Auto {
+        #[serde(skip_serializing_if = "is_false")]
+        disable_parallel_tool_use: bool,
+    },
+    Any {
+        #[serde(skip_serializing_if = "is_false")]
+        disable_parallel_tool_use: bool,
+    },
+    Tool {
+        name: &'a str,
+        #[serde(skip_serializing_if = "is_false")]
+        disable_parallel_tool_use: bool,
+    },
+}
+
+/// `serde`'s `skip_serializing_if` takes a `fn(&T) -> bool`, so this can't just be `Not::not`
+/// (which takes `bool` by value).
+fn is_false(b: &bool) -> bool {
+    !*b
 }
 
 // We map our ToolChoice enum to the Anthropic one that serializes properly
@@ -216,9 +380,16 @@ impl<'a> TryFrom<&'a ToolChoice> for GCPVertexAnthropicToolChoice<'a> {
     type Error = Error;
     fn try_from(tool_choice: &'a ToolChoice) -> Result<Self, Error> {
         match tool_choice {
-            ToolChoice::Auto => Ok(GCPVertexAnthropicToolChoice::Auto),
-            ToolChoice::Required => Ok(GCPVertexAnthropicToolChoice::Any),
-            ToolChoice::Specific(name) => Ok(GCPVertexAnthropicToolChoice::Tool { name }),
+            ToolChoice::Auto => Ok(GCPVertexAnthropicToolChoice::Auto {
+                disable_parallel_tool_use: false,
+            }),
+            ToolChoice::Required => Ok(GCPVertexAnthropicToolChoice::Any {
+                disable_parallel_tool_use: false,
+            }),
+            ToolChoice::Specific(name) => Ok(GCPVertexAnthropicToolChoice::Tool {
+                name,
+                disable_parallel_tool_use: false,
+            }),
             // TODO (#205): Implement ToolChoice::None workaround for Anthropic.
             //              MAKE SURE TO UPDATE THE E2E TESTS WHEN THIS IS DONE.
             ToolChoice::None => Err(Error::InvalidTool {
@@ -251,11 +422,16 @@ impl<'a> From<&'a ToolConfig> for GCPVertexAnthropicTool<'a> {
 #[derive(Clone, Debug, PartialEq, Serialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
-// NB: Anthropic also supports Image blocks here but we won't for now
 enum GCPVertexAnthropicMessageContent<'a> {
     Text {
         text: &'a str,
     },
+    Image {
+        source: GCPVertexAnthropicImageSource,
+    },
+    Document {
+        source: GCPVertexAnthropicDocumentSource,
+    },
     ToolResult {
         tool_use_id: &'a str,
         content: Vec<GCPVertexAnthropicMessageContent<'a>>,
@@ -267,6 +443,68 @@ enum GCPVertexAnthropicMessageContent<'a> {
     },
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GCPVertexAnthropicImageSource {
+    Base64 {
+        media_type: &'static str,
+        data: String,
+    },
+}
+
+/// Maps our `ImageKind` to the `media_type` string Anthropic expects for base64 image blocks.
+fn anthropic_media_type(kind: &ImageKind) -> &'static str {
+    match kind {
+        ImageKind::Png => "image/png",
+        ImageKind::Jpeg => "image/jpeg",
+        ImageKind::WebP => "image/webp",
+        ImageKind::Gif => "image/gif",
+    }
+}
+
+impl From<&Image> for GCPVertexAnthropicMessageContent<'_> {
+    fn from(image: &Image) -> Self {
+        GCPVertexAnthropicMessageContent::Image {
+            source: GCPVertexAnthropicImageSource::Base64 {
+                media_type: anthropic_media_type(&image.mime_type),
+                data: BASE64_STANDARD.encode(&image.data),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GCPVertexAnthropicDocumentSource {
+    Base64 {
+        media_type: &'static str,
+        data: String,
+    },
+}
+
+/// Maps our `FileKind` to the `media_type` string Anthropic expects for base64 document blocks.
+/// Anthropic's `document` content blocks currently only support PDFs.
+///
+/// `File`/`FileKind` already exist in `crate::inference::types` alongside `Image`/`ImageKind`,
+/// which this conversion otherwise mirrors; an earlier commit on this file predated their
+/// addition and assumed they weren't available yet.
+fn anthropic_document_media_type(kind: &FileKind) -> &'static str {
+    match kind {
+        FileKind::Pdf => "application/pdf",
+    }
+}
+
+impl From<&File> for GCPVertexAnthropicMessageContent<'_> {
+    fn from(file: &File) -> Self {
+        GCPVertexAnthropicMessageContent::Document {
+            source: GCPVertexAnthropicDocumentSource::Base64 {
+                media_type: anthropic_document_media_type(&file.mime_type),
+                data: BASE64_STANDARD.encode(&file.data),
+            },
+        }
+    }
+}
+
 impl<'a> TryFrom<&'a ContentBlock> for GCPVertexAnthropicMessageContent<'a> {
     type Error = Error;
 
@@ -275,6 +513,8 @@ impl<'a> TryFrom<&'a ContentBlock> for GCPVertexAnthropicMessageContent<'a> {
             ContentBlock::Text(Text { text }) => {
                 Ok(GCPVertexAnthropicMessageContent::Text { text })
             }
+            ContentBlock::Image(image) => Ok(image.into()),
+            ContentBlock::File(file) => Ok(file.into()),
             ContentBlock::ToolCall(tool_call) => {
                 // Convert the tool call arguments from String to JSON Value (Anthropic expects an object)
                 let input: Value = serde_json::from_str(&tool_call.arguments).map_err(|e| {
@@ -343,40 +583,174 @@ struct GCPVertexAnthropicRequestBody<'a> {
     stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     // This is the system message
-    system: Option<&'a str>,
+    system: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     tool_choice: Option<GCPVertexAnthropicToolChoice<'a>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<GCPVertexAnthropicTool<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
 }
 
 impl<'a> GCPVertexAnthropicRequestBody<'a> {
-    fn new(request: &'a ModelInferenceRequest) -> Result<GCPVertexAnthropicRequestBody<'a>, Error> {
+    fn new(
+        request: &'a ModelInferenceRequest,
+        use_prompted_tools: bool,
+        supports_function_calling: bool,
+        supports_parallel_tool_use: bool,
+    ) -> Result<GCPVertexAnthropicRequestBody<'a>, Error> {
         if request.messages.is_empty() {
             return Err(Error::InvalidRequest {
                 message: "Anthropic requires at least one message".to_string(),
             });
         }
-        let system = request.system.as_deref();
         let request_messages: Vec<GCPVertexAnthropicMessage> = request
             .messages
             .iter()
             .map(GCPVertexAnthropicMessage::try_from)
             .collect::<Result<Vec<_>, _>>()?;
-        let messages = prepare_messages(request_messages)?;
-        let tools = request
+        let messages = prepare_messages(request_messages, request.last_assistant_is_prefill)?;
+        let tools_available = request
             .tool_config
             .as_ref()
-            .map(|c| &c.tools_available)
-            .map(|tools| tools.iter().map(|tool| tool.into()).collect::<Vec<_>>());
-        // `tool_choice` should only be set if tools are set and non-empty
-        let tool_choice: Option<GCPVertexAnthropicToolChoice> = tools
-            .as_ref()
-            .filter(|t| !t.is_empty())
-            .and(request.tool_config.as_ref())
-            .and_then(|c| (&c.tool_choice).try_into().ok());
+            .map(|c| c.tools_available.as_slice())
+            .filter(|t| !t.is_empty());
+
+        let user_stop_sequences = request.stop_sequences.clone().filter(|s| !s.is_empty());
+
+        let (tools, tool_choice, system, stop_sequences) = if use_prompted_tools {
+            match tools_available {
+                Some(tools_available) => {
+                    let mut stop_sequences = user_stop_sequences.unwrap_or_default();
+                    if !stop_sequences
+                        .iter()
+                        .any(|s| s == PROMPTED_TOOLS_STOP_SEQUENCE)
+                    {
+                        stop_sequences.push(PROMPTED_TOOLS_STOP_SEQUENCE.to_string());
+                    }
+                    (
+                        None,
+                        None,
+                        Some(render_prompted_tools_system_prompt(
+                            request.system.as_deref(),
+                            tools_available,
+                        )),
+                        Some(stop_sequences),
+                    )
+                }
+                None => (None, None, request.system.clone(), user_stop_sequences),
+            }
+        } else {
+            let mut tools = tools_available
+                .map(|tools| tools.iter().map(|tool| tool.into()).collect::<Vec<_>>());
+            let mut system = request.system.clone();
+
+            // Anthropic has no native JSON mode. We emulate it by synthesizing a tool whose
+            // `input_schema` is the requested `output_schema` and forcing the model to call it,
+            // then surfacing its arguments as text on the response side.
+            let forced_json_tool = json_mode_forces_tool(request)
+                .then(|| request.output_schema.as_ref())
+                .flatten();
+
+            let tool_choice: Option<GCPVertexAnthropicToolChoice> =
+                if let Some(schema) = forced_json_tool {
+                    tools.get_or_insert_with(Vec::new).push(GCPVertexAnthropicTool {
+                        name: IMPLICIT_TOOL_NAME,
+                        description: Some(
+                            "Respond to the user using this tool, with arguments matching the required JSON schema.",
+                        ),
+                        input_schema: schema,
+                    });
+                    Some(GCPVertexAnthropicToolChoice::Tool {
+                        name: IMPLICIT_TOOL_NAME,
+                        disable_parallel_tool_use: false,
+                    })
+                } else {
+                    // `tool_choice` should only be set if tools are set and non-empty
+                    let requested_tool_choice = tools
+                        .as_ref()
+                        .and(request.tool_config.as_ref())
+                        .map(|c| &c.tool_choice);
+                    match requested_tool_choice {
+                        // Anthropic has no direct equivalent of "tools are available but must not
+                        // be called". We work around this by still advertising `tools` (so the
+                        // model keeps the schema context) but not deriving a `tool_choice` from it
+                        // and telling it not to invoke them via the system prompt instead. If
+                        // parallel tool use is disabled below, the default `auto` choice is still
+                        // sent to carry that flag.
+                        Some(ToolChoice::None) => {
+                            const NO_TOOL_CALLS_INSTRUCTION: &str =
+                                "Do not call any tools; respond only in natural language.";
+                            system = Some(match system {
+                                Some(system) => {
+                                    format!("{system}\n\n{NO_TOOL_CALLS_INSTRUCTION}")
+                                }
+                                None => NO_TOOL_CALLS_INSTRUCTION.to_string(),
+                            });
+                            None
+                        }
+                        Some(tool_choice) => tool_choice.try_into().ok(),
+                        None => None,
+                    }
+                };
+
+            // Force `disable_parallel_tool_use` on for models that can't handle parallel tool
+            // calls, and honor the caller's request to disable it otherwise. Anthropic supports
+            // this flag on `auto`, `any`, and `tool` alike, so it applies regardless of which
+            // tool_choice variant we ended up with.
+            let disable_parallel_tool_use = !supports_parallel_tool_use
+                || request
+                    .tool_config
+                    .as_ref()
+                    .and_then(|c| c.parallel_tool_calls)
+                    .map(|allowed| !allowed)
+                    .unwrap_or(false);
+            let tool_choice = match (tools.as_ref(), tool_choice) {
+                (Some(_), Some(GCPVertexAnthropicToolChoice::Auto { .. })) => {
+                    Some(GCPVertexAnthropicToolChoice::Auto {
+                        disable_parallel_tool_use,
+                    })
+                }
+                (Some(_), Some(GCPVertexAnthropicToolChoice::Any { .. })) => {
+                    Some(GCPVertexAnthropicToolChoice::Any {
+                        disable_parallel_tool_use,
+                    })
+                }
+                (Some(_), Some(GCPVertexAnthropicToolChoice::Tool { name, .. })) => {
+                    Some(GCPVertexAnthropicToolChoice::Tool {
+                        name,
+                        disable_parallel_tool_use,
+                    })
+                }
+                (Some(_), None) if disable_parallel_tool_use => {
+                    Some(GCPVertexAnthropicToolChoice::Auto {
+                        disable_parallel_tool_use,
+                    })
+                }
+                (_, tool_choice) => tool_choice,
+            };
+
+            // Checked against the final `tools` rather than just `tools_available` so that the
+            // synthetic JSON-mode tool (added above when `forced_json_tool` is set) can't bypass
+            // this gate: a model with no native tool-calling support can't be handed any tool
+            // definition, whether it came from the caller or from our own JSON-mode emulation.
+            if tools.is_some() && !supports_function_calling {
+                return Err(Error::InvalidRequest {
+                    message:
+                        "This model does not support function calling, but the request includes tools"
+                            .to_string(),
+                });
+            }
+
+            (tools, tool_choice, system, user_stop_sequences)
+        };
+
         // NOTE: Anthropic does not support seed
         Ok(GCPVertexAnthropicRequestBody {
             anthropic_version: ANTHROPIC_API_VERSION,
@@ -385,8 +759,11 @@ impl<'a> GCPVertexAnthropicRequestBody<'a> {
             stream: Some(request.stream),
             system,
             temperature: request.temperature,
+            top_p: request.top_p,
+            top_k: request.top_k,
             tool_choice,
             tools,
+            stop_sequences,
         })
     }
 }
@@ -396,9 +773,12 @@ impl<'a> GCPVertexAnthropicRequestBody<'a> {
 /// so as to satisfy the API.
 /// It also makes modifications to the messages to make Anthropic happy.
 /// For example, it will prepend a default User message if the first message is an Assistant message.
-/// It will also append a default User message if the last message is an Assistant message.
+/// It will also append a default User message if the last message is an Assistant message, unless
+/// `last_assistant_is_prefill` is set, in which case the trailing assistant message is left as-is
+/// so the model continues generating from it (Anthropic's "assistant prefill").
 fn prepare_messages(
     messages: Vec<GCPVertexAnthropicMessage>,
+    last_assistant_is_prefill: bool,
 ) -> Result<Vec<GCPVertexAnthropicMessage>, Error> {
     let mut consolidated_messages: Vec<GCPVertexAnthropicMessage> = Vec::new();
     let mut last_role: Option<GCPVertexAnthropicRole> = None;
@@ -445,9 +825,9 @@ fn prepare_messages(
     }
     // Anthropic will continue any assistant messages passed in.
     // Since we don't want to do that, we'll append a default User message in the case that the last message was
-    // an assistant message
+    // an assistant message, unless the caller wants that behavior (assistant prefill).
     if let Some(last_message) = consolidated_messages.last() {
-        if last_message.role == GCPVertexAnthropicRole::Assistant {
+        if last_message.role == GCPVertexAnthropicRole::Assistant && !last_assistant_is_prefill {
             consolidated_messages.push(GCPVertexAnthropicMessage {
                 role: GCPVertexAnthropicRole::User,
                 content: vec![GCPVertexAnthropicMessageContent::Text {
@@ -459,6 +839,176 @@ fn prepare_messages(
     Ok(consolidated_messages)
 }
 
+/// Builds the system prompt addendum for "prompted tools" mode: a description of each available
+/// tool plus instructions for how to call one as a `<function_calls>` XML block. Used in place of
+/// the native `tools` field for deployments that don't expose Anthropic's tool-use beta.
+fn render_prompted_tools_system_prompt(system: Option<&str>, tools: &[ToolConfig]) -> String {
+    let mut prompt = String::new();
+    if let Some(system) = system {
+        prompt.push_str(system);
+        prompt.push_str("\n\n");
+    }
+    prompt.push_str(
+        "You have access to the following tools. To call one, respond with a \
+         function_calls block in exactly this format and nothing after it:\n\n\
+         <function_calls>\n\
+         <invoke>\n\
+         <tool_name>TOOL_NAME</tool_name>\n\
+         <parameters>{\"param\": \"value\"}</parameters>\n\
+         </invoke>\n\
+         </function_calls>\n\n\
+         Available tools:\n",
+    );
+    for tool in tools {
+        prompt.push_str(&format!(
+            "- {}: {}\n  Parameters (JSON schema): {}\n",
+            tool.name(),
+            tool.description(),
+            tool.parameters()
+        ));
+    }
+    prompt
+}
+
+/// Parses a `<function_calls>...</function_calls>` block out of prompted-tools output.
+/// Returns any text preceding the block (or the whole string, if no block is present) and the
+/// `ToolCall`s extracted from each `<invoke>` inside it.
+fn parse_prompted_tool_calls(text: &str) -> (Option<String>, Vec<ToolCall>) {
+    let Some(block_start) = text.find(PROMPTED_TOOLS_OPEN_TAG) else {
+        return (Some(text.to_string()).filter(|t| !t.is_empty()), Vec::new());
+    };
+    let leading = text[..block_start].to_string();
+    let block = &text[block_start..];
+    let block = match block.find(PROMPTED_TOOLS_STOP_SEQUENCE) {
+        Some(end) => &block[..end + PROMPTED_TOOLS_STOP_SEQUENCE.len()],
+        None => block,
+    };
+
+    let mut tool_calls = Vec::new();
+    let mut rest = block;
+    while let Some(invoke_start) = rest.find("<invoke>") {
+        let after_open = &rest[invoke_start + "<invoke>".len()..];
+        let Some(invoke_end) = after_open.find("</invoke>") else {
+            break;
+        };
+        let invoke_body = &after_open[..invoke_end];
+        if let (Some(name), Some(parameters)) = (
+            extract_tag(invoke_body, "tool_name"),
+            extract_tag(invoke_body, "parameters"),
+        ) {
+            tool_calls.push(ToolCall {
+                id: Uuid::now_v7().to_string(),
+                name,
+                arguments: parameters,
+            });
+        }
+        rest = &after_open[invoke_end + "</invoke>".len()..];
+    }
+    (Some(leading).filter(|t| !t.trim().is_empty()), tool_calls)
+}
+
+/// Extracts the text between `<tag>` and `</tag>` in `haystack`, if present.
+fn extract_tag(haystack: &str, tag: &str) -> Option<String> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let start = haystack.find(&open)? + open.len();
+    let end = haystack[start..].find(&close)? + start;
+    Some(haystack[start..end].trim().to_string())
+}
+
+/// The length of the longest suffix of `s` that is also a prefix of
+/// [`PROMPTED_TOOLS_OPEN_TAG`], i.e. how many trailing bytes of `s` could still grow into the
+/// opening tag as more text arrives. Operates on bytes so it can't split `s` on a non-UTF8-char
+/// boundary; that's safe here since the tag itself is pure ASCII, so no non-boundary byte offset
+/// could match it anyway.
+fn open_tag_prefix_suffix_len(s: &str) -> usize {
+    let tag = PROMPTED_TOOLS_OPEN_TAG.as_bytes();
+    let bytes = s.as_bytes();
+    let max_len = tag.len().min(bytes.len());
+    (1..=max_len)
+        .rev()
+        .find(|&len| {
+            let start = bytes.len() - len;
+            s.is_char_boundary(start) && bytes[start..] == tag[..len]
+        })
+        .unwrap_or(0)
+}
+
+/// Buffers streamed text while a prompted-tools `<function_calls>` block may be in flight, since
+/// partial XML can't be parsed into a `ToolCallChunk`. Returns `Ok(None)` while still buffering,
+/// and the chunk to forward once it's safe to: unmodified if it carries no text; with its text
+/// replaced by the buffered text that can no longer start the opening tag, if no block has
+/// started; or rewritten into `ToolCallChunk`s (plus any leading text) once the closing tag is
+/// seen, which also empties the buffer.
+fn buffer_prompted_tool_chunk(
+    buffer: &mut String,
+    chunk: ProviderInferenceResponseChunk,
+) -> Result<Option<ProviderInferenceResponseChunk>, Error> {
+    let has_text = chunk
+        .content
+        .iter()
+        .any(|block| matches!(block, ContentBlockChunk::Text(_)));
+    if !has_text {
+        return Ok(Some(chunk));
+    }
+    for block in &chunk.content {
+        if let ContentBlockChunk::Text(text_chunk) = block {
+            buffer.push_str(&text_chunk.text);
+        }
+    }
+
+    if !buffer.contains(PROMPTED_TOOLS_OPEN_TAG) {
+        // No block has started yet. Only the trailing bytes that could still grow into
+        // `<function_calls>` need to stay withheld; everything before that is provably plain
+        // text, so flush it now instead of holding the whole response hostage until it ends. This
+        // is what keeps an ordinary text-only response streaming normally.
+        let held_len = open_tag_prefix_suffix_len(buffer);
+        let safe_len = buffer.len() - held_len;
+        if safe_len == 0 {
+            return Ok(None);
+        }
+        let safe_text: String = buffer.drain(..safe_len).collect();
+        return Ok(Some(ProviderInferenceResponseChunk::new(
+            chunk.inference_id,
+            vec![ContentBlockChunk::Text(TextChunk {
+                text: safe_text,
+                id: "0".to_string(),
+            })],
+            chunk.usage,
+            chunk.raw_response,
+            chunk.latency,
+        )));
+    }
+
+    if !buffer.contains(PROMPTED_TOOLS_STOP_SEQUENCE) {
+        return Ok(None);
+    }
+    // The whole buffer is emitted now, so take it rather than leave it to be flushed again.
+    let buffered = std::mem::take(buffer);
+    let (leading, tool_calls) = parse_prompted_tool_calls(&buffered);
+    let mut content = Vec::new();
+    if let Some(leading) = leading {
+        content.push(ContentBlockChunk::Text(TextChunk {
+            text: leading,
+            id: "0".to_string(),
+        }));
+    }
+    for tool_call in tool_calls {
+        content.push(ContentBlockChunk::ToolCall(ToolCallChunk {
+            id: tool_call.id,
+            raw_name: tool_call.name,
+            raw_arguments: tool_call.arguments,
+        }));
+    }
+    Ok(Some(ProviderInferenceResponseChunk::new(
+        chunk.inference_id,
+        content,
+        chunk.usage,
+        chunk.raw_response,
+        chunk.latency,
+    )))
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 struct GCPVertexAnthropicError {
     error: GCPVertexAnthropicErrorBody,
@@ -481,6 +1031,12 @@ pub enum GCPVertexAnthropicContentBlock {
         name: String,
         input: serde_json::Value,
     },
+    Thinking {
+        thinking: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
 }
 
 impl TryFrom<GCPVertexAnthropicContentBlock> for ContentBlock {
@@ -499,6 +1055,20 @@ impl TryFrom<GCPVertexAnthropicContentBlock> for ContentBlock {
                     })?,
                 }))
             }
+            GCPVertexAnthropicContentBlock::Thinking { thinking } => {
+                Ok(ContentBlock::Thought(Thought { text: thinking }))
+            }
+            // Redacted thinking carries only opaque encrypted data with nothing to show a
+            // consumer of the reasoning stream. There's no `ContentBlock` variant for "nothing to
+            // show"; the caller special-cases this block and drops it instead of calling into
+            // this conversion, mirroring how the streaming path silently drops the equivalent
+            // block. This arm only exists so the match stays exhaustive if that ever changes.
+            GCPVertexAnthropicContentBlock::RedactedThinking { .. } => {
+                Err(Error::AnthropicServer {
+                    message: "Redacted thinking blocks must be filtered out before calling this conversion"
+                        .to_string(),
+                })
+            }
         }
     }
 }
@@ -536,12 +1106,17 @@ struct GCPVertexAnthropicResponse {
 struct GCPVertexAnthropicResponseWithLatency {
     response: GCPVertexAnthropicResponse,
     latency: Latency,
+    use_prompted_tools: bool,
 }
 
 impl TryFrom<GCPVertexAnthropicResponseWithLatency> for ProviderInferenceResponse {
     type Error = Error;
     fn try_from(value: GCPVertexAnthropicResponseWithLatency) -> Result<Self, Self::Error> {
-        let GCPVertexAnthropicResponseWithLatency { response, latency } = value;
+        let GCPVertexAnthropicResponseWithLatency {
+            response,
+            latency,
+            use_prompted_tools,
+        } = value;
 
         let raw_response =
             serde_json::to_string(&response).map_err(|e| Error::AnthropicServer {
@@ -551,8 +1126,48 @@ impl TryFrom<GCPVertexAnthropicResponseWithLatency> for ProviderInferenceRespons
         let content: Vec<ContentBlock> = response
             .content
             .into_iter()
-            .map(|block| block.try_into())
-            .collect::<Result<Vec<_>, _>>()?;
+            .map(|block| -> Result<Vec<ContentBlock>, Error> {
+                // Prompted-tools mode has no native tool_use block: the model emits a
+                // `<function_calls>` XML block as plain text instead. Only scan for it when
+                // prompted tools are actually enabled for this provider, so a native-tools
+                // deployment whose model happens to emit similar-looking example XML doesn't
+                // have that text silently reinterpreted as tool calls.
+                if use_prompted_tools {
+                    if let GCPVertexAnthropicContentBlock::Text { text } = &block {
+                        let (leading_text, tool_calls) = parse_prompted_tool_calls(text);
+                        if !tool_calls.is_empty() {
+                            let mut blocks: Vec<ContentBlock> =
+                                leading_text.map(ContentBlock::from).into_iter().collect();
+                            blocks.extend(tool_calls.into_iter().map(ContentBlock::ToolCall));
+                            return Ok(blocks);
+                        }
+                    }
+                }
+                // Redacted thinking carries only opaque encrypted data with nothing to show a
+                // consumer of the reasoning stream, so it's silently dropped rather than erroring,
+                // mirroring the streaming path.
+                if matches!(
+                    block,
+                    GCPVertexAnthropicContentBlock::RedactedThinking { .. }
+                ) {
+                    return Ok(Vec::new());
+                }
+                let block: ContentBlock = block.try_into()?;
+                // The synthetic JSON-mode tool call isn't a real tool call from the caller's
+                // perspective; surface its arguments as the structured text output instead.
+                if let ContentBlock::ToolCall(tool_call) = &block {
+                    if tool_call.name == IMPLICIT_TOOL_NAME {
+                        return Ok(vec![ContentBlock::Text(Text {
+                            text: tool_call.arguments.clone(),
+                        })]);
+                    }
+                }
+                Ok(vec![block])
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
 
         Ok(ProviderInferenceResponse::new(
             content,
@@ -600,6 +1215,18 @@ enum GCPVertexAnthropicMessageBlock {
     InputJsonDelta {
         partial_json: String,
     },
+    Thinking {
+        thinking: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
 }
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -630,18 +1257,111 @@ enum GCPVertexAnthropicStreamMessage {
     Ping,
 }
 
+/// Attempts to turn a possibly-truncated fragment of streamed tool-call JSON into a syntactically
+/// valid JSON value, so that a stream cut off mid-argument still leaves the caller with something
+/// parseable instead of permanently broken `raw_arguments`.
+///
+/// This tracks a stack of open `{`/`[`, whether we're inside a string (respecting `\"` escapes),
+/// then closes any open string, drops a trailing comma or a dangling `"key":` with no value, and
+/// appends the matching closing brackets in reverse order. Returns the repaired string along with
+/// whether any repair was actually necessary.
+fn repair_partial_json(partial: &str) -> (String, bool) {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    // Tracks the start of the most recently opened string, along with the bracket it was
+    // opened inside of, so we can tell a dangling object *key* (no colon yet) apart from a
+    // dangling array/value *string* (which is already complete on its own).
+    let mut last_string: Option<(usize, Option<char>)> = None;
+    for (i, c) in partial.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                last_string = Some((i, stack.last().copied()));
+            }
+            '{' | '[' => stack.push(c),
+            '}' | ']' => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let mut repaired = partial.to_string();
+    let mut was_repaired = false;
+
+    if in_string {
+        repaired.push('"');
+        was_repaired = true;
+    }
+
+    let trimmed_end = repaired.trim_end();
+    if let Some(without_comma) = trimmed_end.strip_suffix(',') {
+        repaired = without_comma.to_string();
+        was_repaired = true;
+    } else if trimmed_end.ends_with(':') {
+        // A dangling `"key":` with no value; roll back to just after the last separator so we
+        // don't emit a key with no value attached to it.
+        if let Some(pos) = trimmed_end.rfind([',', '{']) {
+            repaired = if trimmed_end.as_bytes()[pos] == b'{' {
+                trimmed_end[..=pos].to_string()
+            } else {
+                trimmed_end[..pos].to_string()
+            };
+            was_repaired = true;
+        }
+    } else if trimmed_end.ends_with('"') {
+        // A dangling object key with no colon (or value) yet, e.g. `{"location"` or, once the
+        // unterminated-string fixup above closes it, `{"loc` -> `{"loc"`. Only rolls back when
+        // the string is actually in key position (directly inside a `{`, right after `{`/`,`);
+        // a string that's already a complete array element or object value is left alone.
+        if let Some((start, Some('{'))) = last_string {
+            let before = partial[..start].trim_end();
+            if before.ends_with('{') || before.ends_with(',') {
+                repaired = before.to_string();
+                was_repaired = true;
+            }
+        }
+    }
+
+    if !stack.is_empty() {
+        was_repaired = true;
+        for open in stack.iter().rev() {
+            repaired.push(match open {
+                '{' => '}',
+                '[' => ']',
+                _ => unreachable!("only '{{' and '[' are ever pushed onto the stack"),
+            });
+        }
+    }
+
+    (repaired, was_repaired)
+}
+
 /// This function converts an Anthropic stream message to a TensorZero stream message.
-/// It must keep track of the current tool ID and name in order to correctly handle ToolCallChunks (which we force to always contain the tool name and ID)
-/// Anthropic only sends the tool ID and name in the ToolUse chunk so we need to keep the most recent ones as mutable references so
-/// subsequent InputJSONDelta chunks can be initialized with this information as well.
+/// It must keep track of each open tool call's ID and name in order to correctly handle
+/// ToolCallChunks (which we force to always contain the tool name and ID), since Anthropic only
+/// sends those in the ToolUse block that starts the call, not in the `InputJsonDelta`s that
+/// follow it. Blocks are keyed by their `index`, which Anthropic includes on both
+/// `ContentBlockStart` and `ContentBlockDelta`, so that parallel tool calls (more than one
+/// `tool_use` block open at once) don't get their arguments attributed to the wrong tool.
 /// There is no need to do the same bookkeeping for TextDelta chunks since they come with an index (which we use as an ID for a text chunk).
 /// See the Anthropic [docs](https://docs.anthropic.com/en/api/messages-streaming) on streaming messages for details on the types of events and their semantics.
 fn anthropic_to_tensorzero_stream_message(
     message: GCPVertexAnthropicStreamMessage,
     inference_id: Uuid,
     message_latency: Duration,
-    current_tool_id: &mut Option<String>,
-    current_tool_name: &mut Option<String>,
+    open_tool_calls: &mut HashMap<u32, (String, String, String)>,
 ) -> Result<Option<ProviderInferenceResponseChunk>, Error> {
     let raw_message = serde_json::to_string(&message).map_err(|e| Error::AnthropicServer {
         message: format!("Error parsing response from Anthropic: {e}"),
@@ -661,25 +1381,62 @@ fn anthropic_to_tensorzero_stream_message(
                 )))
             }
             GCPVertexAnthropicMessageBlock::InputJsonDelta { partial_json } => {
+                // Take the tool name and ID open at this index and use them to create a
+                // ToolCallChunk. This is necessary because the ToolCallChunk must always contain
+                // the tool name and ID even though Anthropic only sends them in the ToolUse block
+                // that starts the call, not in this delta.
+                let (id, raw_name) = {
+                    let entry =
+                        open_tool_calls
+                            .get_mut(&index)
+                            .ok_or(Error::AnthropicServer {
+                                message: format!(
+                                    "Got InputJsonDelta chunk from Anthropic for index {index} without a ToolUse block opening it"
+                                ),
+                            })?;
+                    // Buffered so that `ContentBlockStop` can repair the arguments if the stream
+                    // ends before the tool call's JSON is complete.
+                    entry.2.push_str(&partial_json);
+                    (entry.0.clone(), entry.1.clone())
+                };
+                // The synthetic JSON-mode tool call isn't a real tool call from the caller's
+                // perspective; stream its arguments as text instead.
+                let content = if raw_name == IMPLICIT_TOOL_NAME {
+                    ContentBlockChunk::Text(TextChunk {
+                        text: partial_json,
+                        id,
+                    })
+                } else {
+                    ContentBlockChunk::ToolCall(ToolCallChunk {
+                        id,
+                        raw_name,
+                        raw_arguments: partial_json,
+                    })
+                };
                 Ok(Some(ProviderInferenceResponseChunk::new(
                     inference_id,
-                    // Take the current tool name and ID and use them to create a ToolCallChunk
-                    // This is necessary because the ToolCallChunk must always contain the tool name and ID
-                    // even though Anthropic only sends the tool ID and name in the ToolUse chunk and not InputJSONDelta
-                    vec![ContentBlockChunk::ToolCall(ToolCallChunk {
-                        raw_name: current_tool_name.clone().ok_or(Error::AnthropicServer {
-                            message: "Got InputJsonDelta chunk from Anthropic without current tool name being set by a ToolUse".to_string(),
-                        })?,
-                        id: current_tool_id.clone().ok_or(Error::AnthropicServer {
-                            message: "Got InputJsonDelta chunk from Anthropic without current tool id being set by a ToolUse".to_string(),
-                        })?,
-                        raw_arguments: partial_json,
+                    vec![content],
+                    None,
+                    raw_message,
+                    message_latency,
+                )))
+            }
+            GCPVertexAnthropicMessageBlock::ThinkingDelta { thinking } => {
+                Ok(Some(ProviderInferenceResponseChunk::new(
+                    inference_id,
+                    vec![ContentBlockChunk::Thought(ThoughtChunk {
+                        text: thinking,
+                        id: index.to_string(),
                     })],
                     None,
                     raw_message,
                     message_latency,
                 )))
             }
+            // The signature has no meaning to a consumer displaying the reasoning text, so there
+            // is nothing to forward here; it only matters if the thinking block is replayed back
+            // to Anthropic verbatim.
+            GCPVertexAnthropicMessageBlock::SignatureDelta { .. } => Ok(None),
             _ => Err(Error::AnthropicServer {
                 message: "Unsupported content block type for ContentBlockDelta".to_string(),
             }),
@@ -702,27 +1459,77 @@ fn anthropic_to_tensorzero_stream_message(
                 )))
             }
             GCPVertexAnthropicMessageBlock::ToolUse { id, name, .. } => {
-                // This is a new tool call, update the ID for future chunks
-                *current_tool_id = Some(id.clone());
-                *current_tool_name = Some(name.clone());
-                Ok(Some(ProviderInferenceResponseChunk::new(
-                    inference_id,
-                    vec![ContentBlockChunk::ToolCall(ToolCallChunk {
+                // This is a new tool call at this index; record it for subsequent deltas.
+                open_tool_calls.insert(index, (id.clone(), name.clone(), String::new()));
+                // The synthetic JSON-mode tool call isn't a real tool call from the caller's
+                // perspective; stream it as text instead.
+                let content = if name == IMPLICIT_TOOL_NAME {
+                    ContentBlockChunk::Text(TextChunk {
+                        text: "".to_string(),
+                        id,
+                    })
+                } else {
+                    ContentBlockChunk::ToolCall(ToolCallChunk {
                         id,
                         raw_name: name,
                         // As far as I can tell this is always {} so we ignore
                         raw_arguments: "".to_string(),
+                    })
+                };
+                Ok(Some(ProviderInferenceResponseChunk::new(
+                    inference_id,
+                    vec![content],
+                    None,
+                    raw_message,
+                    message_latency,
+                )))
+            }
+            GCPVertexAnthropicMessageBlock::Thinking { thinking } => {
+                Ok(Some(ProviderInferenceResponseChunk::new(
+                    inference_id,
+                    vec![ContentBlockChunk::Thought(ThoughtChunk {
+                        text: thinking,
+                        id: index.to_string(),
                     })],
                     None,
                     raw_message,
                     message_latency,
                 )))
             }
+            // Redacted thinking carries only opaque encrypted data with nothing to show a
+            // consumer of the reasoning stream, so it's silently dropped rather than erroring.
+            GCPVertexAnthropicMessageBlock::RedactedThinking { .. } => Ok(None),
             _ => Err(Error::AnthropicServer {
                 message: "Unsupported content block type for ContentBlockStart".to_string(),
             }),
         },
-        GCPVertexAnthropicStreamMessage::ContentBlockStop { .. } => Ok(None),
+        GCPVertexAnthropicStreamMessage::ContentBlockStop { index } => {
+            let Some((id, raw_name, buffer)) = open_tool_calls.remove(&index) else {
+                return Ok(None);
+            };
+            // The synthetic JSON-mode tool call's arguments were already streamed as text, not
+            // as a tool call, so there's nothing to repair or re-emit here.
+            if raw_name == IMPLICIT_TOOL_NAME {
+                return Ok(None);
+            }
+            let (repaired, was_repaired) = repair_partial_json(&buffer);
+            if !was_repaired {
+                // The accumulated arguments were already complete and valid; the deltas we
+                // already streamed are sufficient and there's nothing to correct.
+                return Ok(None);
+            }
+            Ok(Some(ProviderInferenceResponseChunk::new(
+                inference_id,
+                vec![ContentBlockChunk::ToolCall(ToolCallChunk {
+                    id,
+                    raw_name,
+                    raw_arguments: repaired,
+                })],
+                None,
+                raw_message,
+                message_latency,
+            )))
+        }
         GCPVertexAnthropicStreamMessage::Error { error } => Err(Error::AnthropicServer {
             message: error.to_string(),
         }),
@@ -771,6 +1578,132 @@ fn parse_usage_info(usage_info: &Value) -> GCPVertexAnthropic {
     }
 }
 
+/// Multi-step ("agentic") tool-calling support built on top of [`InferenceProvider`]. The
+/// provider itself only ever completes a single turn; this module repeatedly re-issues the
+/// request, dispatching every `ContentBlock::ToolCall` the model emits to a caller-registered
+/// handler and feeding the results back, until the model stops calling tools.
+pub mod tool_loop {
+    use std::future::Future;
+    use std::pin::Pin;
+
+    use futures::stream::{self, StreamExt};
+
+    use crate::error::Error;
+    use crate::inference::providers::provider_trait::InferenceProvider;
+    use crate::inference::types::{
+        ContentBlock, ModelInferenceRequest, ProviderInferenceResponse, RequestMessage, Role,
+    };
+    use crate::tool::{ToolCall, ToolResult};
+
+    /// A caller-registered handler for a single tool call. Returns the tool's result as text; an
+    /// `Err` is turned into an error-string `ToolResult` by [`run_tool_conversation`] rather than
+    /// aborting the loop, so one failing tool doesn't take down the whole conversation.
+    pub type ToolHandler<'a> = &'a (dyn Fn(&ToolCall) -> Pin<Box<dyn Future<Output = Result<String, Error>> + Send>>
+                 + Sync);
+
+    /// Pulls every `ContentBlock::ToolCall` out of a response's content, in order.
+    fn tool_calls_in(content: &[ContentBlock]) -> Vec<&ToolCall> {
+        content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolCall(tool_call) => Some(tool_call),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Drives `provider` to completion: on every turn whose response contains one or more
+    /// `ToolCall`s, dispatches them to `handler` and appends the results as a `ToolResult` message
+    /// before re-issuing the request, until a turn comes back with no tool calls or `max_steps`
+    /// turns have elapsed.
+    ///
+    /// When a turn emits more than one tool call, handlers run concurrently (bounded to the
+    /// number of CPUs) and their results are collected back in the same order as the calls, so
+    /// the appended `ToolResult`s line up with their `tool_use_id`s. `prepare_messages`'s
+    /// same-role consolidation (used when the next request is built) then merges them into the
+    /// single user turn Anthropic requires.
+    pub async fn run_tool_conversation<'a, P>(
+        provider: &P,
+        http_client: &reqwest::Client,
+        mut request: ModelInferenceRequest<'a>,
+        handler: ToolHandler<'a>,
+        max_steps: u32,
+    ) -> Result<ProviderInferenceResponse, Error>
+    where
+        P: InferenceProvider + Sync,
+    {
+        for _ in 0..max_steps {
+            let response = provider.infer(&request, http_client).await?;
+            let tool_calls = tool_calls_in(&response.content);
+            if tool_calls.is_empty() {
+                return Ok(response);
+            }
+
+            let worker_count = std::thread::available_parallelism()
+                .map(std::num::NonZeroUsize::get)
+                .unwrap_or(1);
+            let results: Vec<ToolResult> = stream::iter(tool_calls.into_iter().map(|tool_call| async move {
+                let result = handler(tool_call)
+                    .await
+                    .unwrap_or_else(|e| format!("Error calling tool \"{}\": {e}", tool_call.name));
+                ToolResult {
+                    id: tool_call.id.clone(),
+                    name: tool_call.name.clone(),
+                    result,
+                }
+            }))
+            .buffered(worker_count)
+            .collect()
+            .await;
+
+            request.messages.push(RequestMessage {
+                role: Role::Assistant,
+                content: response.content.clone(),
+            });
+            request.messages.extend(results.into_iter().map(|result| RequestMessage {
+                role: Role::User,
+                content: vec![ContentBlock::ToolResult(result)],
+            }));
+        }
+
+        Err(Error::InferenceClient {
+            message: format!("Tool-calling loop exceeded max_steps ({max_steps}) without a final response"),
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_tool_calls_in() {
+            let content = vec![
+                "hello".to_string().into(),
+                ContentBlock::ToolCall(ToolCall {
+                    id: "1".to_string(),
+                    name: "get_weather".to_string(),
+                    arguments: "{}".to_string(),
+                }),
+                ContentBlock::ToolCall(ToolCall {
+                    id: "2".to_string(),
+                    name: "get_time".to_string(),
+                    arguments: "{}".to_string(),
+                }),
+            ];
+            let tool_calls = tool_calls_in(&content);
+            assert_eq!(tool_calls.len(), 2);
+            assert_eq!(tool_calls[0].id, "1");
+            assert_eq!(tool_calls[1].id, "2");
+        }
+
+        #[test]
+        fn test_tool_calls_in_empty() {
+            let content = vec!["hello".to_string().into()];
+            assert!(tool_calls_in(&content).is_empty());
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
@@ -803,7 +1736,9 @@ mod tests {
         assert!(anthropic_tool_choice.is_ok());
         assert_eq!(
             anthropic_tool_choice.unwrap(),
-            GCPVertexAnthropicToolChoice::Auto
+            GCPVertexAnthropicToolChoice::Auto {
+                disable_parallel_tool_use: false
+            }
         );
 
         let tool_choice = ToolChoice::Required;
@@ -811,7 +1746,9 @@ mod tests {
         assert!(anthropic_tool_choice.is_ok());
         assert_eq!(
             anthropic_tool_choice.unwrap(),
-            GCPVertexAnthropicToolChoice::Any
+            GCPVertexAnthropicToolChoice::Any {
+                disable_parallel_tool_use: false
+            }
         );
 
         let tool_choice = ToolChoice::Specific("test".to_string());
@@ -819,7 +1756,10 @@ mod tests {
         assert!(anthropic_tool_choice.is_ok());
         assert_eq!(
             anthropic_tool_choice.unwrap(),
-            GCPVertexAnthropicToolChoice::Tool { name: "test" }
+            GCPVertexAnthropicToolChoice::Tool {
+                name: "test",
+                disable_parallel_tool_use: false
+            }
         );
     }
 
@@ -875,6 +1815,38 @@ mod tests {
                 input: json!({"type": "string"})
             }
         );
+
+        let image_content_block = ContentBlock::Image(Image {
+            data: vec![1, 2, 3, 4],
+            mime_type: ImageKind::Png,
+        });
+        let anthropic_content_block =
+            GCPVertexAnthropicMessageContent::try_from(&image_content_block).unwrap();
+        assert_eq!(
+            anthropic_content_block,
+            GCPVertexAnthropicMessageContent::Image {
+                source: GCPVertexAnthropicImageSource::Base64 {
+                    media_type: "image/png",
+                    data: BASE64_STANDARD.encode([1, 2, 3, 4]),
+                },
+            }
+        );
+
+        let file_content_block = ContentBlock::File(File {
+            data: vec![1, 2, 3, 4],
+            mime_type: FileKind::Pdf,
+        });
+        let anthropic_content_block =
+            GCPVertexAnthropicMessageContent::try_from(&file_content_block).unwrap();
+        assert_eq!(
+            anthropic_content_block,
+            GCPVertexAnthropicMessageContent::Document {
+                source: GCPVertexAnthropicDocumentSource::Base64 {
+                    media_type: "application/pdf",
+                    data: BASE64_STANDARD.encode([1, 2, 3, 4]),
+                },
+            }
+        );
     }
 
     #[test]
@@ -956,8 +1928,13 @@ mod tests {
             json_mode: ModelInferenceRequestJsonMode::Off,
             function_type: FunctionType::Chat,
             output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
         };
-        let anthropic_request_body = GCPVertexAnthropicRequestBody::new(&inference_request);
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true);
         assert!(anthropic_request_body.is_err());
         assert_eq!(
             anthropic_request_body.err().unwrap(),
@@ -988,8 +1965,13 @@ mod tests {
             json_mode: ModelInferenceRequestJsonMode::Off,
             function_type: FunctionType::Chat,
             output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
         };
-        let anthropic_request_body = GCPVertexAnthropicRequestBody::new(&inference_request);
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true);
         assert!(anthropic_request_body.is_ok());
         assert_eq!(
             anthropic_request_body.unwrap(),
@@ -1002,10 +1984,13 @@ mod tests {
                 ],
                 max_tokens: 4096,
                 stream: Some(false),
-                system: Some("test_system"),
+                system: Some("test_system".to_string()),
                 temperature: None,
+                top_p: None,
+                top_k: None,
                 tool_choice: None,
                 tools: None,
+                stop_sequences: None,
             }
         );
 
@@ -1036,8 +2021,13 @@ mod tests {
             json_mode: ModelInferenceRequestJsonMode::On,
             function_type: FunctionType::Chat,
             output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
         };
-        let anthropic_request_body = GCPVertexAnthropicRequestBody::new(&inference_request);
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true);
         assert!(anthropic_request_body.is_ok());
         assert_eq!(
             anthropic_request_body.unwrap(),
@@ -1056,10 +2046,13 @@ mod tests {
                 ],
                 max_tokens: 100,
                 stream: Some(true),
-                system: Some("test_system"),
+                system: Some("test_system".to_string()),
                 temperature: Some(0.5),
+                top_p: None,
+                top_k: None,
                 tool_choice: None,
                 tools: None,
+                stop_sequences: None,
             }
         );
 
@@ -1094,9 +2087,14 @@ mod tests {
             json_mode: ModelInferenceRequestJsonMode::On,
             function_type: FunctionType::Chat,
             output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
         };
 
-        let anthropic_request_body = GCPVertexAnthropicRequestBody::new(&inference_request);
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true);
         assert!(anthropic_request_body.is_ok());
         assert_eq!(
             anthropic_request_body.unwrap(),
@@ -1109,16 +2107,20 @@ mod tests {
                 ],
                 max_tokens: 100,
                 stream: Some(true),
-                system: Some("test_system"),
+                system: Some("test_system".to_string()),
                 temperature: Some(0.5),
+                top_p: None,
+                top_k: None,
                 tool_choice: Some(GCPVertexAnthropicToolChoice::Tool {
                     name: "get_temperature",
+                    disable_parallel_tool_use: false,
                 }),
                 tools: Some(vec![GCPVertexAnthropicTool {
                     name: WEATHER_TOOL.name(),
                     description: Some(WEATHER_TOOL.description()),
                     input_schema: WEATHER_TOOL.parameters(),
                 }]),
+                stop_sequences: None,
             }
         );
     }
@@ -1153,7 +2155,7 @@ mod tests {
             },
             listening_message.clone(),
         ];
-        assert_eq!(prepare_messages(messages.clone()).unwrap(), expected);
+        assert_eq!(prepare_messages(messages.clone(), false).unwrap(), expected);
 
         // Test case 2: Consolidation needed
         let messages = vec![
@@ -1188,7 +2190,7 @@ mod tests {
             },
             listening_message.clone(),
         ];
-        assert_eq!(prepare_messages(messages.clone()).unwrap(), expected);
+        assert_eq!(prepare_messages(messages.clone(), false).unwrap(), expected);
 
         // Test case 3: Multiple consolidations needed
         let messages = vec![
@@ -1234,12 +2236,12 @@ mod tests {
             },
             listening_message.clone(),
         ];
-        assert_eq!(prepare_messages(messages.clone()).unwrap(), expected);
+        assert_eq!(prepare_messages(messages.clone(), false).unwrap(), expected);
 
         // Test case 4: No messages
         let messages: Vec<GCPVertexAnthropicMessage> = vec![];
         let expected: Vec<GCPVertexAnthropicMessage> = vec![listening_message.clone()];
-        assert_eq!(prepare_messages(messages.clone()).unwrap(), expected);
+        assert_eq!(prepare_messages(messages.clone(), false).unwrap(), expected);
 
         // Test case 5: Single message
         let messages = vec![GCPVertexAnthropicMessage {
@@ -1250,7 +2252,7 @@ mod tests {
             role: GCPVertexAnthropicRole::User,
             content: vec![GCPVertexAnthropicMessageContent::Text { text: "Hello" }],
         }];
-        assert_eq!(prepare_messages(messages.clone()).unwrap(), expected);
+        assert_eq!(prepare_messages(messages.clone(), false).unwrap(), expected);
 
         // Test case 6: Consolidate tool uses
         let messages = vec![
@@ -1290,7 +2292,7 @@ mod tests {
                 },
             ],
         }];
-        assert_eq!(prepare_messages(messages.clone()).unwrap(), expected);
+        assert_eq!(prepare_messages(messages.clone(), false).unwrap(), expected);
 
         // Test case 7: Consolidate mixed text and tool use
         let messages = vec![
@@ -1333,7 +2335,42 @@ mod tests {
                 },
             ],
         }];
-        assert_eq!(prepare_messages(messages.clone()).unwrap(), expected);
+        assert_eq!(prepare_messages(messages.clone(), false).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_prepare_messages_assistant_prefill() {
+        let messages = vec![
+            GCPVertexAnthropicMessage {
+                role: GCPVertexAnthropicRole::User,
+                content: vec![GCPVertexAnthropicMessageContent::Text {
+                    text: "Continue this JSON object:",
+                }],
+            },
+            GCPVertexAnthropicMessage {
+                role: GCPVertexAnthropicRole::Assistant,
+                content: vec![GCPVertexAnthropicMessageContent::Text { text: "{" }],
+            },
+        ];
+        // With the flag unset, a trailing assistant message still gets a `[listening]` User
+        // message appended, same as today.
+        assert_eq!(
+            prepare_messages(messages.clone(), false).unwrap(),
+            vec![
+                messages[0].clone(),
+                messages[1].clone(),
+                GCPVertexAnthropicMessage {
+                    role: GCPVertexAnthropicRole::User,
+                    content: vec![GCPVertexAnthropicMessageContent::Text { text: "[listening]" }],
+                },
+            ]
+        );
+        // With the flag set, the trailing assistant message is left as the prefill for the model
+        // to continue from.
+        assert_eq!(
+            prepare_messages(messages.clone(), true).unwrap(),
+            messages
+        );
     }
 
     #[test]
@@ -1429,6 +2466,7 @@ mod tests {
         let body_with_latency = GCPVertexAnthropicResponseWithLatency {
             response: anthropic_response_body.clone(),
             latency: latency.clone(),
+            use_prompted_tools: false,
         };
 
         let inference_response = ProviderInferenceResponse::try_from(body_with_latency).unwrap();
@@ -1464,6 +2502,7 @@ mod tests {
         let body_with_latency = GCPVertexAnthropicResponseWithLatency {
             response: anthropic_response_body.clone(),
             latency: latency.clone(),
+            use_prompted_tools: false,
         };
 
         let inference_response: ProviderInferenceResponse = body_with_latency.try_into().unwrap();
@@ -1509,6 +2548,7 @@ mod tests {
         let body_with_latency = GCPVertexAnthropicResponseWithLatency {
             response: anthropic_response_body.clone(),
             latency: latency.clone(),
+            use_prompted_tools: false,
         };
         let inference_response = ProviderInferenceResponse::try_from(body_with_latency).unwrap();
         assert_eq!(
@@ -1534,15 +2574,150 @@ mod tests {
     }
 
     #[test]
-    fn test_anthropic_to_tensorzero_stream_message() {
-        use serde_json::json;
-        use uuid::Uuid;
-
+    fn test_anthropic_response_conversion_with_thinking() {
+        let anthropic_response_body = GCPVertexAnthropicResponse {
+            id: "1".to_string(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content: vec![
+                GCPVertexAnthropicContentBlock::Thinking {
+                    thinking: "Let me work through this.".to_string(),
+                },
+                GCPVertexAnthropicContentBlock::RedactedThinking {
+                    data: "encrypted-blob".to_string(),
+                },
+                GCPVertexAnthropicContentBlock::Text {
+                    text: "The answer is 4.".to_string(),
+                },
+            ],
+            model: "model-name".to_string(),
+            stop_reason: Some("end_turn".to_string()),
+            stop_sequence: None,
+            usage: GCPVertexAnthropic {
+                input_tokens: 100,
+                output_tokens: 50,
+            },
+        };
+        let body_with_latency = GCPVertexAnthropicResponseWithLatency {
+            response: anthropic_response_body,
+            latency: Latency::NonStreaming {
+                response_time: Duration::from_millis(100),
+            },
+            use_prompted_tools: false,
+        };
+
+        // Parsing the `thinking` block doesn't blow up the whole response (the original bug:
+        // `GCPVertexAnthropicContentBlock` had no `Thinking`/`RedactedThinking` variants, so
+        // `serde_json::from_str` would have failed before we even got here), the redacted block
+        // is dropped, and the trailing text survives.
+        let inference_response = ProviderInferenceResponse::try_from(body_with_latency).unwrap();
+        assert_eq!(inference_response.content.len(), 2);
+        assert_eq!(
+            inference_response.content[0],
+            ContentBlock::Thought(Thought {
+                text: "Let me work through this.".to_string(),
+            })
+        );
+        assert_eq!(
+            inference_response.content[1],
+            "The answer is 4.".to_string().into()
+        );
+    }
+
+    #[test]
+    fn test_prompted_tool_calls_only_parsed_when_prompted_tools_enabled() {
+        let anthropic_response_body = GCPVertexAnthropicResponse {
+            id: "1".to_string(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content: vec![GCPVertexAnthropicContentBlock::Text {
+                text: "Let me check.\n<function_calls>\n<invoke><tool_name>get_weather</tool_name><parameters>{}</parameters></invoke>\n</function_calls>".to_string(),
+            }],
+            model: "model-name".to_string(),
+            stop_reason: Some("end_turn".to_string()),
+            stop_sequence: None,
+            usage: GCPVertexAnthropic {
+                input_tokens: 10,
+                output_tokens: 5,
+            },
+        };
+        let latency = Latency::NonStreaming {
+            response_time: Duration::from_millis(100),
+        };
+
+        // With prompted tools enabled, the XML block is parsed out into a tool call.
+        let body_with_latency = GCPVertexAnthropicResponseWithLatency {
+            response: anthropic_response_body.clone(),
+            latency: latency.clone(),
+            use_prompted_tools: true,
+        };
+        let inference_response = ProviderInferenceResponse::try_from(body_with_latency).unwrap();
+        assert_eq!(inference_response.content.len(), 2);
+        match &inference_response.content[1] {
+            ContentBlock::ToolCall(tool_call) => {
+                assert_eq!(tool_call.name, "get_weather");
+                assert_eq!(tool_call.arguments, "{}");
+            }
+            other => panic!("Expected a tool call content block, got {other:?}"),
+        }
+
+        // With prompted tools disabled (the native tools path), the same text is left as-is
+        // even though it happens to contain `<function_calls>` XML.
+        let body_with_latency = GCPVertexAnthropicResponseWithLatency {
+            response: anthropic_response_body,
+            latency,
+            use_prompted_tools: false,
+        };
+        let inference_response = ProviderInferenceResponse::try_from(body_with_latency).unwrap();
+        assert_eq!(inference_response.content.len(), 1);
+        match &inference_response.content[0] {
+            ContentBlock::Text(text) => assert!(text.text.contains("<function_calls>")),
+            other => panic!("Expected the raw text to be preserved, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_json_mode_tool_call_surfaced_as_text() {
+        let anthropic_response_body = GCPVertexAnthropicResponse {
+            id: "1".to_string(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content: vec![GCPVertexAnthropicContentBlock::ToolUse {
+                id: "tool_call_1".to_string(),
+                name: IMPLICIT_TOOL_NAME.to_string(),
+                input: json!({"answer": "Paris"}),
+            }],
+            model: "model-name".to_string(),
+            stop_reason: Some("tool_use".to_string()),
+            stop_sequence: None,
+            usage: GCPVertexAnthropic {
+                input_tokens: 10,
+                output_tokens: 5,
+            },
+        };
+        let body_with_latency = GCPVertexAnthropicResponseWithLatency {
+            response: anthropic_response_body,
+            latency: Latency::NonStreaming {
+                response_time: Duration::from_millis(100),
+            },
+            use_prompted_tools: false,
+        };
+        let inference_response = ProviderInferenceResponse::try_from(body_with_latency).unwrap();
+        assert_eq!(
+            inference_response.content,
+            vec![r#"{"answer":"Paris"}"#.to_string().into()]
+        );
+    }
+
+    #[test]
+    fn test_anthropic_to_tensorzero_stream_message() {
+        use serde_json::json;
+        use uuid::Uuid;
+
         let inference_id = Uuid::now_v7();
 
         // Test ContentBlockDelta with TextDelta
-        let mut current_tool_id = None;
-        let mut current_tool_name = None;
+        let mut open_tool_calls = HashMap::new();
         let content_block_delta = GCPVertexAnthropicStreamMessage::ContentBlockDelta {
             delta: GCPVertexAnthropicMessageBlock::TextDelta {
                 text: "Hello".to_string(),
@@ -1554,8 +2729,7 @@ mod tests {
             content_block_delta,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         assert!(result.is_ok());
         let chunk = result.unwrap().unwrap();
@@ -1569,9 +2743,8 @@ mod tests {
         }
         assert_eq!(chunk.latency, latency);
 
-        // Test ContentBlockDelta with InputJsonDelta but no previous tool info
-        let mut current_tool_id = None;
-        let mut current_tool_name = None;
+        // Test ContentBlockDelta with InputJsonDelta but no ToolUse opened this index
+        let mut open_tool_calls = HashMap::new();
         let content_block_delta = GCPVertexAnthropicStreamMessage::ContentBlockDelta {
             delta: GCPVertexAnthropicMessageBlock::InputJsonDelta {
                 partial_json: "aaaa: bbbbb".to_string(),
@@ -1583,20 +2756,19 @@ mod tests {
             content_block_delta,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         let error = result.unwrap_err();
         assert_eq!(
             error,
             Error::AnthropicServer {
-                message: "Got InputJsonDelta chunk from Anthropic without current tool name being set by a ToolUse".to_string()
+                message: "Got InputJsonDelta chunk from Anthropic for index 0 without a ToolUse block opening it".to_string()
             }
         );
 
-        // Test ContentBlockDelta with InputJsonDelta and previous tool info
-        let mut current_tool_id = Some("tool_id".to_string());
-        let mut current_tool_name = Some("tool_name".to_string());
+        // Test ContentBlockDelta with InputJsonDelta and a previously opened tool call
+        let mut open_tool_calls = HashMap::new();
+        open_tool_calls.insert(0, ("tool_id".to_string(), "tool_name".to_string(), String::new()));
         let content_block_delta = GCPVertexAnthropicStreamMessage::ContentBlockDelta {
             delta: GCPVertexAnthropicMessageBlock::InputJsonDelta {
                 partial_json: "aaaa: bbbbb".to_string(),
@@ -1608,8 +2780,7 @@ mod tests {
             content_block_delta,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         let chunk = result.unwrap().unwrap();
         assert_eq!(chunk.content.len(), 1);
@@ -1624,8 +2795,7 @@ mod tests {
         assert_eq!(chunk.latency, latency);
 
         // Test ContentBlockStart with ToolUse
-        let mut current_tool_id = None;
-        let mut current_tool_name = None;
+        let mut open_tool_calls = HashMap::new();
         let content_block_start = GCPVertexAnthropicStreamMessage::ContentBlockStart {
             content_block: GCPVertexAnthropicMessageBlock::ToolUse {
                 id: "tool1".to_string(),
@@ -1639,8 +2809,7 @@ mod tests {
             content_block_start,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         let chunk = result.unwrap().unwrap();
         assert_eq!(chunk.content.len(), 1);
@@ -1653,12 +2822,65 @@ mod tests {
             _ => panic!("Expected a tool call content block"),
         }
         assert_eq!(chunk.latency, latency);
-        assert_eq!(current_tool_id, Some("tool1".to_string()));
-        assert_eq!(current_tool_name, Some("calculator".to_string()));
+        assert_eq!(
+            open_tool_calls.get(&1),
+            Some(&("tool1".to_string(), "calculator".to_string(), "".to_string()))
+        );
+
+        // Test two ToolUse blocks open at once (parallel tool calls) don't clobber each other
+        let mut open_tool_calls = HashMap::new();
+        for (index, id, name) in [(0u32, "tool_a", "get_weather"), (1, "tool_b", "get_time")] {
+            let content_block_start = GCPVertexAnthropicStreamMessage::ContentBlockStart {
+                content_block: GCPVertexAnthropicMessageBlock::ToolUse {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    input: json!({}),
+                },
+                index,
+            };
+            anthropic_to_tensorzero_stream_message(
+                content_block_start,
+                inference_id,
+                latency,
+                &mut open_tool_calls,
+            )
+            .unwrap();
+        }
+        // A delta for index 1 should be attributed to tool_b, not whichever ToolUse came last.
+        let content_block_delta = GCPVertexAnthropicStreamMessage::ContentBlockDelta {
+            delta: GCPVertexAnthropicMessageBlock::InputJsonDelta {
+                partial_json: "{\"hour\":".to_string(),
+            },
+            index: 1,
+        };
+        let chunk = anthropic_to_tensorzero_stream_message(
+            content_block_delta,
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap()
+        .unwrap();
+        match &chunk.content[0] {
+            ContentBlockChunk::ToolCall(tool_call) => {
+                assert_eq!(tool_call.id, "tool_b");
+                assert_eq!(tool_call.raw_name, "get_time");
+            }
+            _ => panic!("Expected a tool call content block"),
+        }
+        // ContentBlockStop for index 1 should only forget that index.
+        anthropic_to_tensorzero_stream_message(
+            GCPVertexAnthropicStreamMessage::ContentBlockStop { index: 1 },
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap();
+        assert!(!open_tool_calls.contains_key(&1));
+        assert!(open_tool_calls.contains_key(&0));
 
         // Test ContentBlockStart with Text
-        let mut current_tool_id = None;
-        let mut current_tool_name = None;
+        let mut open_tool_calls = HashMap::new();
         let content_block_start = GCPVertexAnthropicStreamMessage::ContentBlockStart {
             content_block: GCPVertexAnthropicMessageBlock::Text {
                 text: "Hello".to_string(),
@@ -1670,8 +2892,7 @@ mod tests {
             content_block_start,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         let chunk = result.unwrap().unwrap();
         assert_eq!(chunk.content.len(), 1);
@@ -1685,8 +2906,6 @@ mod tests {
         assert_eq!(chunk.latency, latency);
 
         // Test ContentBlockStart with InputJsonDelta (should fail)
-        let mut current_tool_id = None;
-        let mut current_tool_name = None;
         let content_block_start = GCPVertexAnthropicStreamMessage::ContentBlockStart {
             content_block: GCPVertexAnthropicMessageBlock::InputJsonDelta {
                 partial_json: "aaaa: bbbbb".to_string(),
@@ -1698,8 +2917,7 @@ mod tests {
             content_block_start,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         let error = result.unwrap_err();
         assert_eq!(
@@ -1716,8 +2934,7 @@ mod tests {
             content_block_stop,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         assert!(result.is_ok());
         assert!(result.unwrap().is_none());
@@ -1731,8 +2948,7 @@ mod tests {
             error_message,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         assert!(result.is_err());
         assert_eq!(
@@ -1752,8 +2968,7 @@ mod tests {
             message_delta,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         assert!(result.is_ok());
         let chunk = result.unwrap().unwrap();
@@ -1773,8 +2988,7 @@ mod tests {
             message_start,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         assert!(result.is_ok());
         let chunk = result.unwrap().unwrap();
@@ -1792,8 +3006,7 @@ mod tests {
             message_stop,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         assert!(result.is_ok());
         assert!(result.unwrap().is_none());
@@ -1805,13 +3018,249 @@ mod tests {
             ping,
             inference_id,
             latency,
-            &mut current_tool_id,
-            &mut current_tool_name,
+            &mut open_tool_calls,
         );
         assert!(result.is_ok());
         assert!(result.unwrap().is_none());
     }
 
+    #[test]
+    fn test_thinking_stream_message() {
+        use uuid::Uuid;
+
+        let inference_id = Uuid::now_v7();
+        let mut open_tool_calls = HashMap::new();
+        let latency = Duration::from_millis(100);
+
+        // A thinking block opens with (usually empty) reasoning text...
+        let content_block_start = GCPVertexAnthropicStreamMessage::ContentBlockStart {
+            content_block: GCPVertexAnthropicMessageBlock::Thinking {
+                thinking: "".to_string(),
+            },
+            index: 0,
+        };
+        let chunk = anthropic_to_tensorzero_stream_message(
+            content_block_start,
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap()
+        .unwrap();
+        match &chunk.content[0] {
+            ContentBlockChunk::Thought(thought) => {
+                assert_eq!(thought.text, "");
+                assert_eq!(thought.id, "0");
+            }
+            _ => panic!("Expected a thought content block"),
+        }
+
+        // ...then the reasoning streams in as thinking_delta chunks...
+        let content_block_delta = GCPVertexAnthropicStreamMessage::ContentBlockDelta {
+            delta: GCPVertexAnthropicMessageBlock::ThinkingDelta {
+                thinking: "Let me think".to_string(),
+            },
+            index: 0,
+        };
+        let chunk = anthropic_to_tensorzero_stream_message(
+            content_block_delta,
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap()
+        .unwrap();
+        match &chunk.content[0] {
+            ContentBlockChunk::Thought(thought) => {
+                assert_eq!(thought.text, "Let me think");
+                assert_eq!(thought.id, "0");
+            }
+            _ => panic!("Expected a thought content block"),
+        }
+
+        // ...and a trailing signature_delta doesn't crash the stream, it's just dropped.
+        let signature_delta = GCPVertexAnthropicStreamMessage::ContentBlockDelta {
+            delta: GCPVertexAnthropicMessageBlock::SignatureDelta {
+                signature: "sig".to_string(),
+            },
+            index: 0,
+        };
+        let result = anthropic_to_tensorzero_stream_message(
+            signature_delta,
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        );
+        assert!(result.unwrap().is_none());
+
+        // Redacted thinking blocks also don't crash the stream.
+        let redacted = GCPVertexAnthropicStreamMessage::ContentBlockStart {
+            content_block: GCPVertexAnthropicMessageBlock::RedactedThinking {
+                data: "opaque".to_string(),
+            },
+            index: 1,
+        };
+        let result = anthropic_to_tensorzero_stream_message(
+            redacted,
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        );
+        assert!(result.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_repair_partial_json() {
+        // Already-valid JSON is returned unchanged and flagged as not repaired.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"location":"Paris"}"#);
+        assert_eq!(repaired, r#"{"location":"Paris"}"#);
+        assert!(!was_repaired);
+
+        // An open string gets closed, and the still-open object gets closed too.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"location":"Par"#);
+        assert_eq!(repaired, r#"{"location":"Par"}"#);
+        assert!(was_repaired);
+        assert!(serde_json::from_str::<Value>(&repaired).is_ok());
+
+        // An escaped quote inside a string doesn't end it early.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"note":"a \"quoted\" wor"#);
+        assert!(was_repaired);
+        assert!(serde_json::from_str::<Value>(&repaired).is_ok());
+
+        // A trailing comma is dropped.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"a":1,"#);
+        assert_eq!(repaired, r#"{"a":1}"#);
+        assert!(was_repaired);
+
+        // A dangling key with no value rolls back to the last complete entry.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"a":1,"b":"#);
+        assert_eq!(repaired, r#"{"a":1}"#);
+        assert!(was_repaired);
+
+        // Nested open braces/brackets are all closed, in reverse order.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"outer":{"items":[1,2,"#);
+        assert_eq!(repaired, r#"{"outer":{"items":[1,2]}}"#);
+        assert!(was_repaired);
+        assert!(serde_json::from_str::<Value>(&repaired).is_ok());
+
+        // A dangling key right after an opening brace rolls back to just the brace, which is
+        // then closed like any other still-open object.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"a":"#);
+        assert_eq!(repaired, r#"{}"#);
+        assert!(was_repaired);
+
+        // A key truncated before its colon even arrives (complete string, no `:` yet) rolls
+        // back the same way a colon-with-no-value does.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"location""#);
+        assert_eq!(repaired, r#"{}"#);
+        assert!(was_repaired);
+        assert!(serde_json::from_str::<Value>(&repaired).is_ok());
+
+        // ...and the same holds when the key string itself is still mid-truncation.
+        let (repaired, was_repaired) = repair_partial_json(r#"{"loc"#);
+        assert_eq!(repaired, r#"{}"#);
+        assert!(was_repaired);
+        assert!(serde_json::from_str::<Value>(&repaired).is_ok());
+
+        // A string that's a complete array element (not an object key) is left alone even
+        // though it's preceded by `,`/`[`.
+        let (repaired, was_repaired) = repair_partial_json(r#"["a","b"#);
+        assert_eq!(repaired, r#"["a","b"]"#);
+        assert!(was_repaired);
+        assert!(serde_json::from_str::<Value>(&repaired).is_ok());
+    }
+
+    #[test]
+    fn test_tool_call_stream_stop_repairs_truncated_arguments() {
+        use uuid::Uuid;
+
+        let inference_id = Uuid::now_v7();
+        let latency = Duration::from_millis(100);
+        let mut open_tool_calls = HashMap::new();
+
+        anthropic_to_tensorzero_stream_message(
+            GCPVertexAnthropicStreamMessage::ContentBlockStart {
+                content_block: GCPVertexAnthropicMessageBlock::ToolUse {
+                    id: "tool1".to_string(),
+                    name: "get_weather".to_string(),
+                    input: serde_json::json!({}),
+                },
+                index: 0,
+            },
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap();
+        anthropic_to_tensorzero_stream_message(
+            GCPVertexAnthropicStreamMessage::ContentBlockDelta {
+                delta: GCPVertexAnthropicMessageBlock::InputJsonDelta {
+                    partial_json: r#"{"location":"Par"#.to_string(),
+                },
+                index: 0,
+            },
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap();
+
+        // The stream gets cut off before the closing quote and brace arrive.
+        let chunk = anthropic_to_tensorzero_stream_message(
+            GCPVertexAnthropicStreamMessage::ContentBlockStop { index: 0 },
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap()
+        .unwrap();
+        match &chunk.content[0] {
+            ContentBlockChunk::ToolCall(tool_call) => {
+                assert_eq!(tool_call.id, "tool1");
+                assert_eq!(tool_call.raw_name, "get_weather");
+                assert!(serde_json::from_str::<Value>(&tool_call.raw_arguments).is_ok());
+            }
+            _ => panic!("Expected a tool call content block"),
+        }
+        assert!(!open_tool_calls.contains_key(&0));
+
+        // A tool call whose arguments were already complete produces no extra chunk at stop.
+        let mut open_tool_calls = HashMap::new();
+        anthropic_to_tensorzero_stream_message(
+            GCPVertexAnthropicStreamMessage::ContentBlockStart {
+                content_block: GCPVertexAnthropicMessageBlock::ToolUse {
+                    id: "tool2".to_string(),
+                    name: "get_weather".to_string(),
+                    input: serde_json::json!({}),
+                },
+                index: 0,
+            },
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap();
+        anthropic_to_tensorzero_stream_message(
+            GCPVertexAnthropicStreamMessage::ContentBlockDelta {
+                delta: GCPVertexAnthropicMessageBlock::InputJsonDelta {
+                    partial_json: r#"{"location":"Paris"}"#.to_string(),
+                },
+                index: 0,
+            },
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        )
+        .unwrap();
+        let result = anthropic_to_tensorzero_stream_message(
+            GCPVertexAnthropicStreamMessage::ContentBlockStop { index: 0 },
+            inference_id,
+            latency,
+            &mut open_tool_calls,
+        );
+        assert!(result.unwrap().is_none());
+    }
+
     #[test]
     fn test_parse_usage_info() {
         // Test with valid input
@@ -1846,4 +3295,717 @@ mod tests {
         assert_eq!(result.input_tokens, 0);
         assert_eq!(result.output_tokens, 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_tool_choice_none_workaround() {
+        let mut tool_config = WEATHER_TOOL_CONFIG.clone();
+        tool_config.tool_choice = ToolChoice::None;
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["what's the weather?".to_string().into()],
+            }],
+            system: Some("test_system".to_string()),
+            tool_config: Some(Cow::Owned(tool_config)),
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true).unwrap();
+        // Tools are still advertised so the model keeps the schema context...
+        assert_eq!(
+            anthropic_request_body.tools,
+            Some(vec![GCPVertexAnthropicTool {
+                name: WEATHER_TOOL.name(),
+                description: Some(WEATHER_TOOL.description()),
+                input_schema: WEATHER_TOOL.parameters(),
+            }])
+        );
+        // ...but `tool_choice` is left unset and the system prompt is told not to use them.
+        assert_eq!(anthropic_request_body.tool_choice, None);
+        assert_eq!(
+            anthropic_request_body.system,
+            Some(
+                "test_system\n\nDo not call any tools; respond only in natural language."
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_supports_function_calling_rejects_tools_up_front() {
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["what's the weather?".to_string().into()],
+            }],
+            system: None,
+            tool_config: Some(Cow::Borrowed(&WEATHER_TOOL_CONFIG)),
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        // `supports_function_calling: false` should fail fast instead of letting Anthropic
+        // reject the request with a server error.
+        let error =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, false, true).unwrap_err();
+        assert_eq!(
+            error,
+            Error::InvalidRequest {
+                message:
+                    "This model does not support function calling, but the request includes tools"
+                        .to_string(),
+            }
+        );
+        // A request with no tools is unaffected.
+        let no_tools_request = ModelInferenceRequest {
+            tool_config: None,
+            ..inference_request
+        };
+        assert!(GCPVertexAnthropicRequestBody::new(&no_tools_request, false, false, true).is_ok());
+    }
+
+    #[test]
+    fn test_supports_function_calling_does_not_block_prompted_tools() {
+        // A model with no native tool-use support is exactly the scenario `use_prompted_tools`
+        // exists for; it must not be rejected by the `supports_function_calling` gate, which
+        // only applies to the native-tools path.
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["what's the weather?".to_string().into()],
+            }],
+            system: None,
+            tool_config: Some(Cow::Borrowed(&WEATHER_TOOL_CONFIG)),
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, true, false, true).unwrap();
+        assert!(request_body.tools.is_none());
+        assert!(request_body
+            .system
+            .as_deref()
+            .is_some_and(|s| s.contains("get_weather")));
+    }
+
+    #[test]
+    fn test_disable_parallel_tool_use() {
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["what's the weather?".to_string().into()],
+            }],
+            system: None,
+            tool_config: Some(Cow::Borrowed(&WEATHER_TOOL_CONFIG)),
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+
+        // A model that can't handle parallel tool calls always gets `disable_parallel_tool_use`,
+        // even though the request itself doesn't ask for it.
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, false).unwrap();
+        assert_eq!(
+            anthropic_request_body.tool_choice,
+            Some(GCPVertexAnthropicToolChoice::Tool {
+                name: "get_temperature",
+                disable_parallel_tool_use: true
+            })
+        );
+
+        // A model that does support parallel tool calls doesn't force it off by default.
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true).unwrap();
+        assert_eq!(
+            anthropic_request_body.tool_choice,
+            Some(GCPVertexAnthropicToolChoice::Tool {
+                name: "get_temperature",
+                disable_parallel_tool_use: false
+            })
+        );
+
+        // The caller can still ask for it explicitly even on a model that supports parallel calls.
+        let mut tool_config = WEATHER_TOOL_CONFIG.clone();
+        tool_config.parallel_tool_calls = Some(false);
+        let disabled_by_caller_request = ModelInferenceRequest {
+            tool_config: Some(Cow::Owned(tool_config)),
+            ..inference_request
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&disabled_by_caller_request, false, true, true)
+                .unwrap();
+        assert_eq!(
+            anthropic_request_body.tool_choice,
+            Some(GCPVertexAnthropicToolChoice::Auto {
+                disable_parallel_tool_use: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_disable_parallel_tool_use_applies_to_any_and_tool_choice() {
+        // `disable_parallel_tool_use` isn't `auto`-only: Anthropic accepts it alongside `any` and
+        // `tool` too, so a model that can't handle parallel calls must get it set no matter which
+        // tool_choice the request resolves to.
+        let mut required_tool_config = WEATHER_TOOL_CONFIG.clone();
+        required_tool_config.tool_choice = ToolChoice::Required;
+        let required_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["what's the weather?".to_string().into()],
+            }],
+            system: None,
+            tool_config: Some(Cow::Owned(required_tool_config)),
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&required_request, false, true, false).unwrap();
+        assert_eq!(
+            anthropic_request_body.tool_choice,
+            Some(GCPVertexAnthropicToolChoice::Any {
+                disable_parallel_tool_use: true
+            })
+        );
+
+        let mut specific_tool_config = WEATHER_TOOL_CONFIG.clone();
+        specific_tool_config.tool_choice = ToolChoice::Specific("get_temperature".to_string());
+        let specific_request = ModelInferenceRequest {
+            tool_config: Some(Cow::Owned(specific_tool_config)),
+            ..required_request
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&specific_request, false, true, false).unwrap();
+        assert_eq!(
+            anthropic_request_body.tool_choice,
+            Some(GCPVertexAnthropicToolChoice::Tool {
+                name: "get_temperature",
+                disable_parallel_tool_use: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_extra_body() {
+        let mut base = json!({
+            "max_tokens": 100,
+            "metadata": {"user_id": "abc"},
+        });
+        let overlay = json!({
+            "max_tokens": 200,
+            "metadata": {"session_id": "xyz"},
+            "top_k": 40,
+        });
+        let Value::Object(overlay) = overlay else {
+            unreachable!()
+        };
+        merge_extra_body(&mut base, &overlay).unwrap();
+        assert_eq!(
+            base,
+            json!({
+                // `extra_body` keys win over what we generated...
+                "max_tokens": 200,
+                // ...but nested objects are merged rather than replaced wholesale...
+                "metadata": {"user_id": "abc", "session_id": "xyz"},
+                // ...and brand-new keys are simply added.
+                "top_k": 40,
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_extra_body_rejects_reserved_keys() {
+        for reserved_key in RESERVED_EXTRA_BODY_KEYS {
+            let mut base = json!({"max_tokens": 100});
+            let overlay = json!({ *reserved_key: "whatever" });
+            let Value::Object(overlay) = overlay else {
+                unreachable!()
+            };
+            let error = merge_extra_body(&mut base, &overlay).unwrap_err();
+            assert_eq!(
+                error,
+                Error::InvalidRequest {
+                    message: format!("`extra_body` may not set reserved field `{reserved_key}`"),
+                }
+            );
+            // The reservation is enforced before any mutation, so `base` is untouched.
+            assert_eq!(base, json!({"max_tokens": 100}));
+        }
+    }
+
+    // End-to-end coverage of `extra_body` precedence through `build_request_body`, for the
+    // raw-passthrough behavior `merge_extra_body` already implements (see `test_merge_extra_body`
+    // for unit-level coverage of the merge itself).
+    #[test]
+    fn test_build_request_body_extra_body_precedence() {
+        let provider = GCPVertexAnthropicProvider {
+            request_url: "https://example.com".to_string(),
+            streaming_request_url: "https://example.com".to_string(),
+            audience: "https://example.com".to_string(),
+            credentials: None,
+            model_id: "claude".to_string(),
+            use_prompted_tools: false,
+            extra_body: Some(
+                json!({
+                    // Overrides a field this crate already generates...
+                    "max_tokens": 9999,
+                    // ...and adds a provider-native parameter this crate doesn't model yet.
+                    "thinking": {"type": "enabled", "budget_tokens": 1024},
+                })
+                .as_object()
+                .unwrap()
+                .clone(),
+            ),
+            supports_function_calling: true,
+            supports_parallel_tool_use: true,
+        };
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["hello".to_string().into()],
+            }],
+            system: None,
+            tool_config: None,
+            temperature: None,
+            max_tokens: Some(100),
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let request_body = provider.build_request_body(&inference_request).unwrap();
+        // `extra_body` wins over the typed field we generated...
+        assert_eq!(request_body["max_tokens"], json!(9999));
+        // ...and a brand-new, not-yet-typed field is passed straight through.
+        assert_eq!(
+            request_body["thinking"],
+            json!({"type": "enabled", "budget_tokens": 1024})
+        );
+    }
+
+    #[test]
+    fn test_build_request_body_rejects_reserved_extra_body_key() {
+        let provider = GCPVertexAnthropicProvider {
+            request_url: "https://example.com".to_string(),
+            streaming_request_url: "https://example.com".to_string(),
+            audience: "https://example.com".to_string(),
+            credentials: None,
+            model_id: "claude".to_string(),
+            use_prompted_tools: false,
+            extra_body: Some(json!({"messages": []}).as_object().unwrap().clone()),
+            supports_function_calling: true,
+            supports_parallel_tool_use: true,
+        };
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["hello".to_string().into()],
+            }],
+            system: None,
+            tool_config: None,
+            temperature: None,
+            max_tokens: Some(100),
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let error = provider.build_request_body(&inference_request).unwrap_err();
+        assert_eq!(
+            error,
+            Error::InvalidRequest {
+                message: "`extra_body` may not set reserved field `messages`".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_sampling_params_request_body() {
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["hello".to_string().into()],
+            }],
+            system: None,
+            tool_config: None,
+            temperature: Some(0.7),
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: Some(0.9),
+            top_k: Some(40),
+            last_assistant_is_prefill: false,
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true).unwrap();
+        assert_eq!(anthropic_request_body.top_p, Some(0.9));
+        assert_eq!(anthropic_request_body.top_k, Some(40));
+    }
+
+    #[test]
+    fn test_stop_sequences_request_body() {
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["hello".to_string().into()],
+            }],
+            system: None,
+            tool_config: None,
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: Some(vec!["STOP".to_string()]),
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true).unwrap();
+        assert_eq!(
+            anthropic_request_body.stop_sequences,
+            Some(vec!["STOP".to_string()])
+        );
+
+        // An empty list is treated the same as not setting it, so it's omitted from the body.
+        let inference_request_empty = ModelInferenceRequest {
+            stop_sequences: Some(vec![]),
+            ..inference_request
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request_empty, false, true, true)
+                .unwrap();
+        assert_eq!(anthropic_request_body.stop_sequences, None);
+    }
+
+    #[test]
+    fn test_json_mode_forces_synthetic_tool() {
+        let schema = json!({"type": "object", "properties": {"answer": {"type": "string"}}});
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["what's the capital of France?".to_string().into()],
+            }],
+            system: None,
+            tool_config: None,
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::On,
+            function_type: FunctionType::Chat,
+            output_schema: Some(schema.clone()),
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, true, true).unwrap();
+        let tools = anthropic_request_body.tools.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, IMPLICIT_TOOL_NAME);
+        assert_eq!(tools[0].input_schema, &schema);
+        assert_eq!(
+            anthropic_request_body.tool_choice,
+            Some(GCPVertexAnthropicToolChoice::Tool {
+                name: IMPLICIT_TOOL_NAME,
+                disable_parallel_tool_use: false
+            })
+        );
+
+        // Without an output_schema there's nothing to force a tool call with, so json_mode alone
+        // leaves the request unchanged.
+        let inference_request_no_schema = ModelInferenceRequest {
+            output_schema: None,
+            ..inference_request
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request_no_schema, false, true, true)
+                .unwrap();
+        assert_eq!(anthropic_request_body.tools, None);
+        assert_eq!(anthropic_request_body.tool_choice, None);
+    }
+
+    #[test]
+    fn test_supports_function_calling_rejects_synthetic_json_mode_tool() {
+        // A model with `supports_function_calling: false` and no user-provided tools must still
+        // be rejected once JSON mode synthesizes its own tool; it can't receive any tool
+        // definition, whether it came from the caller or from our own JSON-mode emulation.
+        let schema = json!({"type": "object", "properties": {"answer": {"type": "string"}}});
+        let inference_request = ModelInferenceRequest {
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: vec!["what's the capital of France?".to_string().into()],
+            }],
+            system: None,
+            tool_config: None,
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::On,
+            function_type: FunctionType::Chat,
+            output_schema: Some(schema),
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let error =
+            GCPVertexAnthropicRequestBody::new(&inference_request, false, false, true).unwrap_err();
+        assert_eq!(
+            error,
+            Error::InvalidRequest {
+                message:
+                    "This model does not support function calling, but the request includes tools"
+                        .to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_prompted_tools_request_body() {
+        let messages = vec![RequestMessage {
+            role: Role::User,
+            content: vec!["what's the weather?".to_string().into()],
+        }];
+        let inference_request = ModelInferenceRequest {
+            messages: messages.clone(),
+            system: Some("test_system".to_string()),
+            tool_config: Some(Cow::Borrowed(&WEATHER_TOOL_CONFIG)),
+            temperature: None,
+            max_tokens: None,
+            seed: None,
+            stream: false,
+            json_mode: ModelInferenceRequestJsonMode::Off,
+            function_type: FunctionType::Chat,
+            output_schema: None,
+            stop_sequences: None,
+            top_p: None,
+            top_k: None,
+            last_assistant_is_prefill: false,
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request, true, true, true).unwrap();
+        // Tools are never sent natively in prompted-tools mode.
+        assert_eq!(anthropic_request_body.tools, None);
+        assert_eq!(anthropic_request_body.tool_choice, None);
+        assert_eq!(
+            anthropic_request_body.stop_sequences,
+            Some(vec![PROMPTED_TOOLS_STOP_SEQUENCE.to_string()])
+        );
+        let system = anthropic_request_body.system.unwrap();
+        assert!(system.starts_with("test_system"));
+        assert!(system.contains(WEATHER_TOOL.name()));
+        assert!(system.contains("<function_calls>"));
+
+        // With no tools configured, prompted-tools mode is a no-op on top of the plain system message.
+        let inference_request_no_tools = ModelInferenceRequest {
+            tool_config: None,
+            ..inference_request
+        };
+        let anthropic_request_body =
+            GCPVertexAnthropicRequestBody::new(&inference_request_no_tools, true, true, true)
+                .unwrap();
+        assert_eq!(anthropic_request_body.system, Some("test_system".to_string()));
+        assert_eq!(anthropic_request_body.stop_sequences, None);
+    }
+
+    #[test]
+    fn test_parse_prompted_tool_calls() {
+        // No function_calls block: the whole string comes back as leading text
+        let (leading, tool_calls) = parse_prompted_tool_calls("just some text");
+        assert_eq!(leading, Some("just some text".to_string()));
+        assert!(tool_calls.is_empty());
+
+        // A single invoke
+        let text = "Let me check that.\n<function_calls>\n<invoke>\n<tool_name>get_temperature</tool_name>\n<parameters>{\"location\": \"Tokyo\"}</parameters>\n</invoke>\n</function_calls>";
+        let (leading, tool_calls) = parse_prompted_tool_calls(text);
+        assert_eq!(leading, Some("Let me check that.\n".to_string()));
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].name, "get_temperature");
+        assert_eq!(tool_calls[0].arguments, r#"{"location": "Tokyo"}"#);
+
+        // Multiple invokes (parallel tool calls)
+        let text = "<function_calls>\n<invoke><tool_name>a</tool_name><parameters>{}</parameters></invoke>\n<invoke><tool_name>b</tool_name><parameters>{}</parameters></invoke>\n</function_calls>";
+        let (leading, tool_calls) = parse_prompted_tool_calls(text);
+        assert_eq!(leading, None);
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].name, "a");
+        assert_eq!(tool_calls[1].name, "b");
+    }
+
+    #[test]
+    fn test_buffer_prompted_tool_chunk_flushes_plain_text_immediately() {
+        let inference_id = Uuid::now_v7();
+        let latency = Duration::from_millis(10);
+        let mut buffer = String::new();
+
+        // Text deltas that provably can't be the start of a `<function_calls>` block are flushed
+        // right away instead of being withheld for the whole response; otherwise a plain-text
+        // response would silently degrade from streaming to non-streaming.
+        for text in ["The weather in Tokyo ", "is sunny today."] {
+            let chunk = ProviderInferenceResponseChunk::new(
+                inference_id,
+                vec![ContentBlockChunk::Text(TextChunk {
+                    text: text.to_string(),
+                    id: "0".to_string(),
+                })],
+                None,
+                "{}".to_string(),
+                latency,
+            );
+            let result = buffer_prompted_tool_chunk(&mut buffer, chunk)
+                .unwrap()
+                .unwrap();
+            assert_eq!(result.content.len(), 1);
+            match &result.content[0] {
+                ContentBlockChunk::Text(text_chunk) => assert_eq!(text_chunk.text, text),
+                _ => panic!("Expected a text content block"),
+            }
+        }
+        assert_eq!(buffer, "");
+    }
+
+    #[test]
+    fn test_buffer_prompted_tool_chunk_withholds_partial_open_tag() {
+        let inference_id = Uuid::now_v7();
+        let latency = Duration::from_millis(10);
+        let mut buffer = String::new();
+
+        // A delta that ends mid-tag must be withheld: flushing it would split `<function_calls>`
+        // across two text chunks and this and `parse_prompted_tool_calls` would never see it as a
+        // whole tag.
+        let chunk = ProviderInferenceResponseChunk::new(
+            inference_id,
+            vec![ContentBlockChunk::Text(TextChunk {
+                text: "Let me check.\n<function_c".to_string(),
+                id: "0".to_string(),
+            })],
+            None,
+            "{}".to_string(),
+            latency,
+        );
+        let result = buffer_prompted_tool_chunk(&mut buffer, chunk)
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.content.len(), 1);
+        match &result.content[0] {
+            ContentBlockChunk::Text(text_chunk) => assert_eq!(text_chunk.text, "Let me check.\n"),
+            _ => panic!("Expected a text content block"),
+        }
+        // Only the partial tag prefix is still held.
+        assert_eq!(buffer, "<function_c");
+
+        // Once the rest of the tag arrives it no longer matches a prefix of the open tag (it now
+        // contains the whole thing), so buffering continues until the closing tag instead.
+        let chunk = ProviderInferenceResponseChunk::new(
+            inference_id,
+            vec![ContentBlockChunk::Text(TextChunk {
+                text: "alls>\n<invoke>".to_string(),
+                id: "0".to_string(),
+            })],
+            None,
+            "{}".to_string(),
+            latency,
+        );
+        assert!(buffer_prompted_tool_chunk(&mut buffer, chunk)
+            .unwrap()
+            .is_none());
+        assert_eq!(buffer, "<function_calls>\n<invoke>");
+    }
+
+    #[test]
+    fn test_buffer_prompted_tool_chunk_parses_on_stop_sequence() {
+        let inference_id = Uuid::now_v7();
+        let latency = Duration::from_millis(10);
+        let mut buffer = "Let me check.\n".to_string();
+        let chunk = ProviderInferenceResponseChunk::new(
+            inference_id,
+            vec![ContentBlockChunk::Text(TextChunk {
+                text: "<function_calls>\n<invoke><tool_name>get_weather</tool_name><parameters>{}</parameters></invoke>\n</function_calls>".to_string(),
+                id: "0".to_string(),
+            })],
+            None,
+            "{}".to_string(),
+            latency,
+        );
+        let result = buffer_prompted_tool_chunk(&mut buffer, chunk)
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.content.len(), 2);
+        match &result.content[0] {
+            ContentBlockChunk::Text(text_chunk) => assert_eq!(text_chunk.text, "Let me check.\n"),
+            _ => panic!("Expected leading text chunk"),
+        }
+        match &result.content[1] {
+            ContentBlockChunk::ToolCall(tool_call) => {
+                assert_eq!(tool_call.raw_name, "get_weather")
+            }
+            _ => panic!("Expected a tool call content block"),
+        }
+    }
+}